@@ -0,0 +1,391 @@
+use std::{error::Error, fmt, fs::File, io::BufWriter, path::Path, time::Duration};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rodio::{
+    OutputStream, OutputStreamHandle,
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait},
+    },
+    source::UniformSourceIterator,
+};
+
+use crate::{
+    effects::BoxedSource,
+    playable::SAMPLE_RATE,
+    resample::{Quality, resample},
+};
+
+/// Channels rendered by the offline backends.
+const CHANNELS: u16 = 2;
+/// Frames offline backends render at once, which bounds their memory when rendering long sources to the end.
+const BLOCK_FRAMES: usize = 4096;
+
+#[derive(Debug)]
+pub enum BackendError {
+    /// No audio device could be opened.
+    Stream(rodio::StreamError),
+    Play(rodio::PlayError),
+    Wav(hound::Error),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Stream(e) => write!(f, "failed to open audio output: {}", e),
+            Self::Play(e) => write!(f, "failed to play source: {}", e),
+            Self::Wav(e) => write!(f, "failed to write wav file: {}", e),
+        }
+    }
+}
+
+impl Error for BackendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Stream(e) => Some(e),
+            Self::Play(e) => Some(e),
+            Self::Wav(e) => Some(e),
+        }
+    }
+}
+
+impl From<rodio::StreamError> for BackendError {
+    fn from(value: rodio::StreamError) -> Self {
+        Self::Stream(value)
+    }
+}
+
+impl From<rodio::PlayError> for BackendError {
+    fn from(value: rodio::PlayError) -> Self {
+        Self::Play(value)
+    }
+}
+
+impl From<hound::Error> for BackendError {
+    fn from(value: hound::Error) -> Self {
+        Self::Wav(value)
+    }
+}
+
+/// Somewhere to send rendered audio.
+///
+/// Real-time backends play sources as time passes. Offline backends only render when told to with
+/// [`AudioBackend::advance`], so they run without an audio device and as fast as the machine allows.
+pub trait AudioBackend {
+    /// Starts playing `source` on top of whatever is playing already. Sources at another sample rate are converted.
+    fn play(&mut self, source: BoxedSource) -> Result<(), BackendError>;
+
+    /// Rate the output runs at. Sources rendered at this rate play without conversion.
+    fn sample_rate(&self) -> u32;
+
+    /// Sets the quality of the conversion of sources started from now on.
+    fn set_quality(&mut self, quality: Quality);
+
+    /// Renders the next `duration` of audio. Does nothing on real-time backends, where time passes on its own.
+    fn advance(&mut self, duration: Duration) -> Result<(), BackendError> {
+        let _ = duration;
+        Ok(())
+    }
+
+    /// Renders until every source has ended and closes the output. Sources that never end must be stopped first,
+    /// e.g. with [`crate::engine::EngineHandle::finish`].
+    fn finish(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+/// Plays through the default audio device.
+pub struct RodioBackend {
+    /// Output stops when this is dropped.
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    sample_rate: u32,
+    quality: Quality,
+}
+
+impl RodioBackend {
+    /// Opens the default device at its preferred sample rate.
+    pub fn new() -> Result<Self, BackendError> {
+        Self::with_sample_rate(None)
+    }
+
+    /// Opens the default device at `sample_rate`, or at its preferred rate if it does not support that one.
+    pub fn with_sample_rate(sample_rate: Option<u32>) -> Result<Self, BackendError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(rodio::StreamError::NoDevice)?;
+        let preferred = device
+            .default_output_config()
+            .map_err(rodio::StreamError::DefaultStreamConfigError)?;
+        let config = sample_rate
+            .and_then(|rate| {
+                device
+                    .supported_output_configs()
+                    .ok()?
+                    .filter(|el| el.channels() == preferred.channels())
+                    .find(|el| (el.min_sample_rate().0..=el.max_sample_rate().0).contains(&rate))
+                    .map(|el| el.with_sample_rate(cpal::SampleRate(rate)))
+            })
+            .unwrap_or(preferred);
+        let sample_rate = config.sample_rate().0;
+        let (stream, handle) = OutputStream::try_from_device_config(&device, config)?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+            sample_rate,
+            quality: Quality::default(),
+        })
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, source: BoxedSource) -> Result<(), BackendError> {
+        // Convert here, since rodio's own conversion only interpolates linearly.
+        let source = resample(source, self.sample_rate, self.quality);
+        Ok(self.handle.play_raw(source)?)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn set_quality(&mut self, quality: Quality) {
+        self.quality = quality;
+    }
+}
+
+/// Sums sources converted to a common sample rate and channel count.
+struct OfflineMixer {
+    sample_rate: u32,
+    quality: Quality,
+    sources: Vec<UniformSourceIterator<BoxedSource, f32>>,
+}
+
+impl OfflineMixer {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            quality: Quality::default(),
+            sources: Vec::new(),
+        }
+    }
+
+    fn play(&mut self, source: BoxedSource) {
+        // The rates already match after resampling, so the iterator only converts the channel count.
+        let source = resample(source, self.sample_rate, self.quality);
+        self.sources.push(UniformSourceIterator::new(
+            source,
+            CHANNELS,
+            self.sample_rate,
+        ));
+    }
+
+    /// Renders `frames` interleaved frames, or fewer if every source ends before.
+    fn render(&mut self, frames: usize, until_silent: bool) -> Vec<f32> {
+        let mut out = Vec::new();
+        for _ in 0..frames {
+            if until_silent && self.sources.is_empty() {
+                break;
+            }
+            for _ in 0..CHANNELS {
+                let mut total = 0.;
+                self.sources.retain_mut(|el| match el.next() {
+                    Some(o) => {
+                        total += o;
+                        true
+                    }
+                    None => false,
+                });
+                out.push(total);
+            }
+        }
+        out
+    }
+
+    /// Renders like [`OfflineMixer::render`] a block at a time, throwing the audio away.
+    fn render_blocks(&mut self, frames: usize, until_silent: bool) {
+        let mut left = frames;
+        while left > 0 {
+            let rendered =
+                self.render(left.min(BLOCK_FRAMES), until_silent).len() / CHANNELS as usize;
+            if rendered == 0 {
+                break;
+            }
+            left -= rendered;
+        }
+    }
+
+    fn frames(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.sample_rate as f64).round() as usize
+    }
+}
+
+/// Renders into memory, e.g. to inspect the output in tests or run without an audio device.
+pub struct BufferBackend {
+    mixer: OfflineMixer,
+    samples: Vec<f32>,
+}
+
+impl Default for BufferBackend {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE)
+    }
+}
+
+impl BufferBackend {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            mixer: OfflineMixer::new(sample_rate),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Interleaved stereo samples rendered so far.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Takes the samples rendered so far, leaving the buffer empty.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn channels(&self) -> u16 {
+        CHANNELS
+    }
+}
+
+impl AudioBackend for BufferBackend {
+    fn play(&mut self, source: BoxedSource) -> Result<(), BackendError> {
+        self.mixer.play(source);
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate
+    }
+
+    fn set_quality(&mut self, quality: Quality) {
+        self.mixer.quality = quality;
+    }
+
+    fn advance(&mut self, duration: Duration) -> Result<(), BackendError> {
+        let frames = self.mixer.frames(duration);
+        self.samples.extend(self.mixer.render(frames, false));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), BackendError> {
+        self.samples.extend(self.mixer.render(usize::MAX, true));
+        Ok(())
+    }
+}
+
+/// Renders and throws the audio away, so sources still progress without an audio device.
+pub struct NullBackend(OfflineMixer);
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE)
+    }
+}
+
+impl NullBackend {
+    pub fn new(sample_rate: u32) -> Self {
+        Self(OfflineMixer::new(sample_rate))
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, source: BoxedSource) -> Result<(), BackendError> {
+        self.0.play(source);
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.0.sample_rate
+    }
+
+    fn set_quality(&mut self, quality: Quality) {
+        self.0.quality = quality;
+    }
+
+    fn advance(&mut self, duration: Duration) -> Result<(), BackendError> {
+        let frames = self.0.frames(duration);
+        self.0.render_blocks(frames, false);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), BackendError> {
+        self.0.render_blocks(usize::MAX, true);
+        Ok(())
+    }
+}
+
+/// Renders into a 32-bit float WAV file.
+pub struct WavBackend {
+    mixer: OfflineMixer,
+    /// Taken when the file is finalised.
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavBackend {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> Result<Self, BackendError> {
+        let spec = WavSpec {
+            channels: CHANNELS,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        Ok(Self {
+            mixer: OfflineMixer::new(sample_rate),
+            writer: Some(WavWriter::create(path, spec)?),
+        })
+    }
+
+    /// Renders `frames` frames, or until every source ends, and writes them out a block at a time.
+    fn write(&mut self, frames: usize, until_silent: bool) -> Result<(), BackendError> {
+        let mut left = frames;
+        while left > 0 {
+            let samples = self.mixer.render(left.min(BLOCK_FRAMES), until_silent);
+            if samples.is_empty() {
+                break;
+            }
+            left -= samples.len() / CHANNELS as usize;
+            if let Some(writer) = &mut self.writer {
+                for sample in samples {
+                    writer.write_sample(sample)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AudioBackend for WavBackend {
+    fn play(&mut self, source: BoxedSource) -> Result<(), BackendError> {
+        self.mixer.play(source);
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate
+    }
+
+    fn set_quality(&mut self, quality: Quality) {
+        self.mixer.quality = quality;
+    }
+
+    fn advance(&mut self, duration: Duration) -> Result<(), BackendError> {
+        let frames = self.mixer.frames(duration);
+        self.write(frames, false)
+    }
+
+    fn finish(&mut self) -> Result<(), BackendError> {
+        self.write(usize::MAX, true)?;
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,1143 @@
-use std::fs::File;
+//! Project files, which keep the tracks of a piece and the state of the editor between sessions.
+//!
+//! A project is stored as JSON tagged with the version of its format. Files written in an older format are upgraded
+//! step by step as they are loaded, and files in a newer one are refused rather than misread, as are values no editor
+//! could have written, like a pan beyond either side.
 
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, PoisonError,
+        mpsc::{RecvTimeoutError, Sender, channel},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    effects::{self, Effect},
+    envelope::Adsr,
+    glide::{Glide, GlidePath, Portamento},
+    instrument::{Fm, Instrument, Partial, Pluck, Sample, SampleError, Timbre},
+    modulation::{Lfo, LfoShape, Modulation},
+    note::Harmonym,
+    playable::{Bandlimit, Waveform},
+    score::{NoteDuration, Score, ScoreNote},
+    time::{MAX_BEATS, TempoMap, Ticks, TimeSignature},
+};
+
+/// Version of the format [`Project::to_json`] writes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Upgrades a project file from one format version to the next.
+type Migration = fn(&mut Value) -> Result<(), ProjectError>;
+
+/// Upgrades from each older format version to the next one, starting with version 0.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [add_editor];
+
+/// How often [`Autosave`] writes unsaved changes to the recovery file by default.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(io::Error),
+    /// The file is not JSON, or not laid out like a project.
+    Corrupt(serde_json::Error),
+    /// The file has no format version, or one newer than this program knows.
+    UnsupportedVersion(Option<u64>),
+    /// A value makes no sense, like a tempo change before the start of the score.
+    Invalid(String),
+    Sample(SampleError),
+    /// A sample instrument holds a recording that was not loaded from a file, so there is nothing to refer to.
+    UnsavedSample,
+    /// The session has not been saved before, so it has no file to save to.
+    NoPath,
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to access project file: {}", e),
+            Self::Corrupt(e) => write!(f, "project file is damaged: {}", e),
+            Self::UnsupportedVersion(Some(version)) => write!(
+                f,
+                "project file has format version {}, but only versions 0 to {} can be read",
+                version, FORMAT_VERSION
+            ),
+            Self::UnsupportedVersion(None) => write!(f, "project file has no format version"),
+            Self::Invalid(e) => write!(f, "project file is damaged: {}", e),
+            Self::Sample(e) => write!(f, "failed to load sample of project: {}", e),
+            Self::UnsavedSample => write!(f, "samples not loaded from a file cannot be saved"),
+            Self::NoPath => write!(f, "project has no file to save to"),
+        }
+    }
+}
+
+impl Error for ProjectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Corrupt(e) => Some(e),
+            Self::Sample(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProjectError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for ProjectError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Corrupt(value)
+    }
+}
+
+impl From<SampleError> for ProjectError {
+    fn from(value: SampleError) -> Self {
+        Self::Sample(value)
+    }
+}
+
+/// A score in a project, played along with the other tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub name: String,
+    pub muted: bool,
+    pub score: Score,
+}
+
+/// Where the editor was when the project was saved, and the settings new notes are written with.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EditorState {
+    /// Index of the selected track.
+    pub track: usize,
+    pub cursor: Ticks,
+    pub instrument: Instrument,
+    pub envelope: Adsr,
+    pub modulation: Modulation,
+    pub portamento: Option<Portamento>,
+    pub effects: Vec<Effect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Project {
+    pub tracks: Vec<Track>,
+    pub editor: EditorState,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a project from the text of a project file, upgrading older formats.
+    pub fn from_json(text: &str) -> Result<Self, ProjectError> {
+        let mut value: Value = serde_json::from_str(text)?;
+        let version = value.get("version").and_then(Value::as_u64);
+        let file: ProjectFile = match version {
+            // Parsed again from the text, so errors point at a line and column.
+            Some(version) if version == FORMAT_VERSION as u64 => serde_json::from_str(text)?,
+            Some(version) if version < FORMAT_VERSION as u64 => {
+                for migrate in &MIGRATIONS[version as usize..] {
+                    migrate(&mut value)?;
+                }
+                value["version"] = FORMAT_VERSION.into();
+                serde_json::from_value(value)?
+            }
+            _ => return Err(ProjectError::UnsupportedVersion(version)),
+        };
+        file.try_into()
+    }
+
+    /// The text of a project file holding this project, in the current format.
+    pub fn to_json(&self) -> Result<String, ProjectError> {
+        Ok(serde_json::to_string_pretty(&ProjectFile::try_from(self)?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Saves to `path`, which keeps its old contents if saving fails halfway.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        Ok(write_atomic(path.as_ref(), &self.to_json()?)?)
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and then moves it into place, so `path` holds either the
+/// old or the new contents even if the program dies while writing.
+fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".tmp");
+    let temporary = path.with_file_name(name);
+    let result = File::create(&temporary).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    result
+        .and_then(|_| fs::rename(&temporary, path))
+        .inspect_err(|_| {
+            fs::remove_file(&temporary).ok();
+        })
+}
+
+/// File unsaved changes to the project at `project` are kept in until they are saved, next to the project. Projects
+/// that were never saved share one in the temporary directory.
+pub fn recovery_path(project: Option<&Path>) -> PathBuf {
+    match project {
+        Some(project) => {
+            let mut name = project.file_name().unwrap_or_default().to_owned();
+            name.push(".recovery");
+            project.with_file_name(name)
+        }
+        None => std::env::temp_dir().join("chalaxata-untitled.recovery"),
+    }
+}
+
+/// A project open for editing, along with the file it is kept in.
+#[derive(Debug)]
 pub struct SessionData {
-    file: File,
+    project: Project,
+    path: Option<PathBuf>,
+    /// The project changed since it was last opened or saved.
+    modified: bool,
+    /// The recovery file holds the latest changes, or there are none.
+    autosaved: bool,
+}
+
+impl Default for SessionData {
+    fn default() -> Self {
+        Self {
+            project: Project::default(),
+            path: None,
+            modified: false,
+            autosaved: true,
+        }
+    }
+}
+
+impl SessionData {
+    /// An empty project that has not been saved yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        Ok(Self {
+            project: Project::load(&path)?,
+            path: Some(path.as_ref().to_owned()),
+            ..Self::default()
+        })
+    }
+
+    /// The unsaved changes to the project at `project`, or to a project that was never saved, left behind by a
+    /// session that did not end properly.
+    pub fn recover(project: Option<&Path>) -> Result<Option<Self>, ProjectError> {
+        let recovery = recovery_path(project);
+        if !recovery.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            project: Project::load(recovery)?,
+            path: project.map(Path::to_owned),
+            modified: true,
+            autosaved: true,
+        }))
+    }
+
+    pub fn project(&self) -> &Project {
+        &self.project
+    }
+
+    /// The project for editing, which counts as a change to be saved.
+    pub fn project_mut(&mut self) -> &mut Project {
+        self.modified = true;
+        self.autosaved = false;
+        &mut self.project
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// File the unsaved changes of this session are kept in.
+    pub fn recovery_path(&self) -> PathBuf {
+        recovery_path(self.path())
+    }
+
+    /// Writes the changes made since the last autosave to the recovery file. Returns whether there were any.
+    pub fn autosave(&mut self) -> Result<bool, ProjectError> {
+        if self.autosaved {
+            return Ok(false);
+        }
+        write_atomic(&self.recovery_path(), &self.project.to_json()?)?;
+        self.autosaved = true;
+        Ok(true)
+    }
+
+    /// Removes the recovery file, e.g. when the session ends without a crash.
+    pub fn discard_recovery(&self) -> io::Result<()> {
+        match fs::remove_file(self.recovery_path()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves to the file the project was last opened from or saved to.
+    pub fn save(&mut self) -> Result<(), ProjectError> {
+        let path = self.path.clone().ok_or(ProjectError::NoPath)?;
+        self.save_as(path)
+    }
+
+    /// Saves to `path`, which further saves go to as well.
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        self.project.save(&path)?;
+        // The changes are safe now, including those kept under the old name. A recovery file left behind only means
+        // being offered them again.
+        self.discard_recovery().ok();
+        self.path = Some(path.as_ref().to_owned());
+        self.discard_recovery().ok();
+        self.modified = false;
+        self.autosaved = true;
+        Ok(())
+    }
+}
+
+/// Writes the unsaved changes of a session to its recovery file at a steady interval, until it is dropped.
+pub struct Autosave {
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Autosave {
+    /// Starts autosaving `session` every `interval`, passing failures to `on_error`.
+    pub fn start(
+        session: Arc<Mutex<SessionData>>,
+        interval: Duration,
+        mut on_error: impl FnMut(ProjectError) + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = channel();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let result = session
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .autosave();
+                if let Err(e) = result {
+                    on_error(e);
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        self.stop.send(()).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Version 0 kept only the tracks, so the editor of an upgraded project starts from its defaults.
+fn add_editor(value: &mut Value) -> Result<(), ProjectError> {
+    let Value::Object(project) = value else {
+        return Err(ProjectError::Invalid("project is not an object".to_owned()));
+    };
+    if !project.contains_key("editor") {
+        let editor = EditorFile::try_from(&EditorState::default())?;
+        project.insert("editor".to_owned(), serde_json::to_value(editor)?);
+    }
+    Ok(())
+}
+
+/// Durations are written in milliseconds.
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.as_nanos() as f64 / 1e6)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let ms = f64::deserialize(deserializer)?;
+        if !(0. ..1e12).contains(&ms) {
+            return Err(de::Error::custom(format!("invalid duration of {} ms", ms)));
+        }
+        Ok(Duration::from_nanos((ms * 1e6).round() as u64))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectFile {
+    version: u32,
+    tracks: Vec<TrackFile>,
+    editor: EditorFile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrackFile {
+    name: String,
+    #[serde(default)]
+    muted: bool,
+    base: f32,
+    tempo: TempoFile,
+    instrument: InstrumentFile,
+    #[serde(default)]
+    effects: Vec<EffectFile>,
+    notes: Vec<NoteFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EditorFile {
+    #[serde(default)]
+    track: usize,
+    #[serde(default)]
+    cursor: Ticks,
+    instrument: InstrumentFile,
+    envelope: AdsrFile,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vibrato: Option<LfoFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tremolo: Option<LfoFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    portamento: Option<PortamentoFile>,
+    #[serde(default)]
+    effects: Vec<EffectFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TempoFile {
+    tempos: Vec<TempoChangeFile>,
+    signatures: Vec<SignatureFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TempoChangeFile {
+    at: Ticks,
+    bpm: f64,
+    #[serde(default)]
+    ramp: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignatureFile {
+    bar: u32,
+    beats: u32,
+    unit: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NoteFile {
+    start: Ticks,
+    length: Ticks,
+    /// Exponents of the harmonym, from the octave up.
+    harmonym: [i8; 5],
+    envelope: AdsrFile,
+    #[serde(default)]
+    pan: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    glide: Option<GlideFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vibrato: Option<LfoFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tremolo: Option<LfoFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdsrFile {
+    #[serde(with = "millis")]
+    attack: Duration,
+    #[serde(with = "millis")]
+    decay: Duration,
+    sustain: f32,
+    #[serde(with = "millis")]
+    release: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LfoShapeFile {
+    Sine,
+    Triangle,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LfoFile {
+    shape: LfoShapeFile,
+    rate: f32,
+    depth: f32,
+    #[serde(with = "millis", default)]
+    delay: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GlidePathFile {
+    Direct,
+    Lattice { portamento: f32 },
+}
+
+#[derive(Serialize, Deserialize)]
+struct GlideFile {
+    to: [i8; 5],
+    #[serde(with = "millis", default)]
+    delay: Duration,
+    #[serde(with = "millis")]
+    duration: Duration,
+    path: GlidePathFile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PortamentoFile {
+    #[serde(with = "millis")]
+    duration: Duration,
+    path: GlidePathFile,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WaveformFile {
+    Sine,
+    Square,
+    Saw,
+    Triangle,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BandlimitFile {
+    Naive,
+    Additive,
+    PolyBlep,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InstrumentFile {
+    Wave {
+        waveform: WaveformFile,
+        bandlimit: BandlimitFile,
+    },
+    Additive {
+        /// Ratio and amplitude of each partial.
+        partials: Vec<(f32, f32)>,
+    },
+    Fm {
+        ratio: f32,
+        index: f32,
+        feedback: f32,
+    },
+    Pluck {
+        decay: f32,
+        brightness: f32,
+    },
+    /// Refers to the recording by the path it was loaded from.
+    Sample {
+        path: PathBuf,
+        root: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        looping: Option<(usize, usize)>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EffectFile {
+    LowPass {
+        cutoff: f32,
+        resonance: f32,
+    },
+    Delay {
+        #[serde(with = "millis")]
+        time: Duration,
+        feedback: f32,
+        mix: f32,
+    },
+    Reverb {
+        room_size: f32,
+        damping: f32,
+        mix: f32,
+    },
+}
+
+impl TryFrom<&Project> for ProjectFile {
+    type Error = ProjectError;
+
+    fn try_from(value: &Project) -> Result<Self, Self::Error> {
+        Ok(Self {
+            version: FORMAT_VERSION,
+            tracks: value
+                .tracks
+                .iter()
+                .map(TrackFile::try_from)
+                .collect::<Result<_, _>>()?,
+            editor: (&value.editor).try_into()?,
+        })
+    }
+}
+
+impl TryFrom<ProjectFile> for Project {
+    type Error = ProjectError;
+
+    fn try_from(value: ProjectFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            tracks: value
+                .tracks
+                .into_iter()
+                .map(Track::try_from)
+                .collect::<Result<_, _>>()?,
+            editor: value.editor.try_into()?,
+        })
+    }
+}
+
+impl TryFrom<&Track> for TrackFile {
+    type Error = ProjectError;
+
+    fn try_from(value: &Track) -> Result<Self, Self::Error> {
+        let score = &value.score;
+        Ok(Self {
+            name: value.name.clone(),
+            muted: value.muted,
+            base: score.base(),
+            tempo: score.tempo().into(),
+            instrument: score.instrument().try_into()?,
+            effects: score.effects().iter().map(EffectFile::from).collect(),
+            notes: score.notes().iter().map(NoteFile::from).collect(),
+        })
+    }
+}
+
+impl TryFrom<TrackFile> for Track {
+    type Error = ProjectError;
+
+    fn try_from(value: TrackFile) -> Result<Self, Self::Error> {
+        let mut score = Score::new();
+        score.set_base(within(value.base, POSITIVE, "base frequency")?);
+        score.set_tempo(value.tempo.try_into()?);
+        score.set_instrument(Instrument::try_from(value.instrument)?);
+        score.set_effects(
+            value
+                .effects
+                .into_iter()
+                .map(Effect::try_from)
+                .collect::<Result<_, _>>()?,
+        );
+        for note in value.notes {
+            score.push(ScoreNote::try_from(note)?);
+        }
+        Ok(Self {
+            name: value.name,
+            muted: value.muted,
+            score,
+        })
+    }
+}
+
+impl TryFrom<&EditorState> for EditorFile {
+    type Error = ProjectError;
+
+    fn try_from(value: &EditorState) -> Result<Self, Self::Error> {
+        Ok(Self {
+            track: value.track,
+            cursor: value.cursor,
+            instrument: (&value.instrument).try_into()?,
+            envelope: value.envelope.into(),
+            vibrato: value.modulation.vibrato.map(LfoFile::from),
+            tremolo: value.modulation.tremolo.map(LfoFile::from),
+            portamento: value.portamento.map(PortamentoFile::from),
+            effects: value.effects.iter().map(EffectFile::from).collect(),
+        })
+    }
+}
+
+impl TryFrom<EditorFile> for EditorState {
+    type Error = ProjectError;
+
+    fn try_from(value: EditorFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            track: value.track,
+            cursor: value.cursor,
+            instrument: value.instrument.try_into()?,
+            envelope: value.envelope.try_into()?,
+            modulation: Modulation {
+                vibrato: value.vibrato.map(Lfo::try_from).transpose()?,
+                tremolo: value.tremolo.map(Lfo::try_from).transpose()?,
+            },
+            portamento: value.portamento.map(Portamento::try_from).transpose()?,
+            effects: value
+                .effects
+                .into_iter()
+                .map(Effect::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<&TempoMap> for TempoFile {
+    fn from(value: &TempoMap) -> Self {
+        Self {
+            tempos: value
+                .tempos()
+                .iter()
+                .map(|el| TempoChangeFile {
+                    at: el.at,
+                    bpm: el.bpm,
+                    ramp: el.ramp,
+                })
+                .collect(),
+            signatures: value
+                .time_signatures()
+                .iter()
+                .map(|(bar, signature)| SignatureFile {
+                    bar: *bar,
+                    beats: signature.beats,
+                    unit: signature.unit,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<TempoFile> for TempoMap {
+    type Error = ProjectError;
+
+    fn try_from(value: TempoFile) -> Result<Self, Self::Error> {
+        let tempos = value.tempos;
+        let Some(first) = tempos.first().filter(|el| el.at == 0) else {
+            return Err(ProjectError::Invalid(
+                "tempo map does not start at tick 0".to_owned(),
+            ));
+        };
+        if tempos.windows(2).any(|el| el[0].at >= el[1].at) {
+            return Err(ProjectError::Invalid(
+                "tempo changes are out of order".to_owned(),
+            ));
+        }
+        if let Some(change) = tempos
+            .iter()
+            .find(|el| !(el.bpm > 0. && el.bpm.is_finite()))
+        {
+            return Err(ProjectError::Invalid(format!(
+                "invalid tempo of {} bpm",
+                change.bpm
+            )));
+        }
+        let mut map = TempoMap::new(first.bpm);
+        for change in &tempos[1..] {
+            map.set_tempo(change.at, change.bpm);
+        }
+        // A ramp leads into the change after it, so it can only be laid once both ends are in place.
+        for (change, next) in tempos.iter().zip(&tempos[1..]) {
+            if change.ramp {
+                map.ramp_tempo(change.at, next.at, next.bpm);
+            }
+        }
+        for signature in value.signatures {
+            if signature.bar == 0
+                || !(1..=MAX_BEATS).contains(&signature.beats)
+                || !(1..=256).contains(&signature.unit)
+            {
+                return Err(ProjectError::Invalid(format!(
+                    "invalid time signature {}/{} in bar {}",
+                    signature.beats, signature.unit, signature.bar
+                )));
+            }
+            map.set_time_signature(
+                signature.bar,
+                TimeSignature {
+                    beats: signature.beats,
+                    unit: signature.unit,
+                },
+            );
+        }
+        Ok(map)
+    }
+}
+
+impl From<&ScoreNote> for NoteFile {
+    fn from(value: &ScoreNote) -> Self {
+        Self {
+            start: value.duration.start,
+            length: value.duration.length,
+            harmonym: value.harmonym.exponents(),
+            envelope: value.envelope.into(),
+            pan: value.pan,
+            glide: value.glide.map(GlideFile::from),
+            vibrato: value.modulation.vibrato.map(LfoFile::from),
+            tremolo: value.modulation.tremolo.map(LfoFile::from),
+            base: value.base,
+        }
+    }
+}
+
+impl TryFrom<NoteFile> for ScoreNote {
+    type Error = ProjectError;
+
+    fn try_from(value: NoteFile) -> Result<Self, Self::Error> {
+        if value.start.checked_add(value.length).is_none() {
+            return Err(ProjectError::Invalid(format!(
+                "note of {} ticks at tick {} ends past the longest score",
+                value.length, value.start
+            )));
+        }
+        Ok(Self {
+            duration: NoteDuration {
+                start: value.start,
+                length: value.length,
+            },
+            harmonym: harmonym(value.harmonym)?,
+            envelope: value.envelope.try_into()?,
+            pan: within(value.pan, -1. ..=1., "pan")?,
+            glide: value.glide.map(Glide::try_from).transpose()?,
+            modulation: Modulation {
+                vibrato: value.vibrato.map(Lfo::try_from).transpose()?,
+                tremolo: value.tremolo.map(Lfo::try_from).transpose()?,
+            },
+            base: value
+                .base
+                .map(|el| within(el, POSITIVE, "base frequency"))
+                .transpose()?,
+        })
+    }
+}
+
+fn harmonym(exponents: [i8; 5]) -> Result<Harmonym, ProjectError> {
+    Harmonym::from_exponents(exponents).ok_or_else(|| {
+        ProjectError::Invalid(format!("no harmonym has the exponents {exponents:?}"))
+    })
+}
+
+/// Finite values above 0.
+const POSITIVE: RangeInclusive<f32> = f32::MIN_POSITIVE..=f32::MAX;
+/// Any finite value, for settings the effects and instruments bring into range themselves.
+const FINITE: RangeInclusive<f32> = f32::MIN..=f32::MAX;
+
+/// Passes `value` on if it lies in `range`, which also keeps out NaN.
+fn within(value: f32, range: RangeInclusive<f32>, what: &str) -> Result<f32, ProjectError> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(ProjectError::Invalid(format!("invalid {what} of {value}")))
+    }
+}
+
+impl From<Adsr> for AdsrFile {
+    fn from(value: Adsr) -> Self {
+        Self {
+            attack: value.attack,
+            decay: value.decay,
+            sustain: value.sustain,
+            release: value.release,
+        }
+    }
+}
+
+impl TryFrom<AdsrFile> for Adsr {
+    type Error = ProjectError;
+
+    fn try_from(value: AdsrFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            attack: value.attack,
+            decay: value.decay,
+            sustain: within(value.sustain, 0. ..=1., "sustain level")?,
+            release: value.release,
+        })
+    }
+}
+
+impl From<Lfo> for LfoFile {
+    fn from(value: Lfo) -> Self {
+        Self {
+            shape: match value.shape {
+                LfoShape::Sine => LfoShapeFile::Sine,
+                LfoShape::Triangle => LfoShapeFile::Triangle,
+            },
+            rate: value.rate,
+            depth: value.depth,
+            delay: value.delay,
+        }
+    }
+}
+
+impl TryFrom<LfoFile> for Lfo {
+    type Error = ProjectError;
+
+    fn try_from(value: LfoFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            shape: match value.shape {
+                LfoShapeFile::Sine => LfoShape::Sine,
+                LfoShapeFile::Triangle => LfoShape::Triangle,
+            },
+            rate: within(value.rate, 0. ..=f32::MAX, "modulation rate")?,
+            depth: within(value.depth, 0. ..=f32::MAX, "modulation depth")?,
+            delay: value.delay,
+        })
+    }
+}
+
+impl From<GlidePath> for GlidePathFile {
+    fn from(value: GlidePath) -> Self {
+        match value {
+            GlidePath::Direct => Self::Direct,
+            GlidePath::Lattice { portamento } => Self::Lattice { portamento },
+        }
+    }
+}
+
+impl TryFrom<GlidePathFile> for GlidePath {
+    type Error = ProjectError;
+
+    fn try_from(value: GlidePathFile) -> Result<Self, Self::Error> {
+        Ok(match value {
+            GlidePathFile::Direct => Self::Direct,
+            GlidePathFile::Lattice { portamento } => Self::Lattice {
+                portamento: within(portamento, 0. ..=1., "portamento")?,
+            },
+        })
+    }
+}
+
+impl From<Glide> for GlideFile {
+    fn from(value: Glide) -> Self {
+        Self {
+            to: value.to.exponents(),
+            delay: value.delay,
+            duration: value.duration,
+            path: value.path.into(),
+        }
+    }
+}
+
+impl TryFrom<GlideFile> for Glide {
+    type Error = ProjectError;
+
+    fn try_from(value: GlideFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            to: harmonym(value.to)?,
+            delay: value.delay,
+            duration: value.duration,
+            path: value.path.try_into()?,
+        })
+    }
+}
+
+impl From<Portamento> for PortamentoFile {
+    fn from(value: Portamento) -> Self {
+        Self {
+            duration: value.duration,
+            path: value.path.into(),
+        }
+    }
+}
+
+impl TryFrom<PortamentoFile> for Portamento {
+    type Error = ProjectError;
+
+    fn try_from(value: PortamentoFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            duration: value.duration,
+            path: value.path.try_into()?,
+        })
+    }
+}
+
+impl TryFrom<&Instrument> for InstrumentFile {
+    type Error = ProjectError;
+
+    fn try_from(value: &Instrument) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Instrument::Wave {
+                waveform,
+                bandlimit,
+            } => Self::Wave {
+                waveform: match waveform {
+                    Waveform::Sine => WaveformFile::Sine,
+                    Waveform::Square => WaveformFile::Square,
+                    Waveform::Saw => WaveformFile::Saw,
+                    Waveform::Triangle => WaveformFile::Triangle,
+                },
+                bandlimit: match bandlimit {
+                    Bandlimit::Naive => BandlimitFile::Naive,
+                    Bandlimit::Additive => BandlimitFile::Additive,
+                    Bandlimit::PolyBlep => BandlimitFile::PolyBlep,
+                },
+            },
+            Instrument::Additive(timbre) => Self::Additive {
+                partials: timbre
+                    .partials
+                    .iter()
+                    .map(|el| (el.ratio, el.amplitude))
+                    .collect(),
+            },
+            Instrument::Fm(fm) => Self::Fm {
+                ratio: fm.ratio,
+                index: fm.index,
+                feedback: fm.feedback,
+            },
+            Instrument::Pluck(pluck) => Self::Pluck {
+                decay: pluck.decay,
+                brightness: pluck.brightness,
+            },
+            Instrument::Sample(sample) => Self::Sample {
+                path: sample.path().ok_or(ProjectError::UnsavedSample)?.to_owned(),
+                root: sample.root(),
+                looping: sample.looping(),
+            },
+        })
+    }
+}
+
+impl TryFrom<InstrumentFile> for Instrument {
+    type Error = ProjectError;
+
+    fn try_from(value: InstrumentFile) -> Result<Self, Self::Error> {
+        Ok(match value {
+            InstrumentFile::Wave {
+                waveform,
+                bandlimit,
+            } => Self::Wave {
+                waveform: match waveform {
+                    WaveformFile::Sine => Waveform::Sine,
+                    WaveformFile::Square => Waveform::Square,
+                    WaveformFile::Saw => Waveform::Saw,
+                    WaveformFile::Triangle => Waveform::Triangle,
+                },
+                bandlimit: match bandlimit {
+                    BandlimitFile::Naive => Bandlimit::Naive,
+                    BandlimitFile::Additive => Bandlimit::Additive,
+                    BandlimitFile::PolyBlep => Bandlimit::PolyBlep,
+                },
+            },
+            InstrumentFile::Additive { partials } => Timbre {
+                partials: partials
+                    .into_iter()
+                    .map(|(ratio, amplitude)| {
+                        Ok(Partial {
+                            ratio: within(ratio, FINITE, "partial ratio")?,
+                            amplitude: within(amplitude, FINITE, "partial amplitude")?,
+                        })
+                    })
+                    .collect::<Result<_, ProjectError>>()?,
+            }
+            .into(),
+            InstrumentFile::Fm {
+                ratio,
+                index,
+                feedback,
+            } => Fm {
+                ratio: within(ratio, FINITE, "FM ratio")?,
+                index: within(index, FINITE, "FM index")?,
+                feedback: within(feedback, FINITE, "FM feedback")?,
+            }
+            .into(),
+            InstrumentFile::Pluck { decay, brightness } => Pluck {
+                decay: within(decay, FINITE, "pluck decay")?,
+                brightness: within(brightness, FINITE, "pluck brightness")?,
+            }
+            .into(),
+            InstrumentFile::Sample {
+                path,
+                root,
+                looping,
+            } => {
+                let mut sample = Sample::load(path, root)?;
+                if let Some((start, end)) = looping {
+                    sample.set_loop(start, end)?;
+                }
+                Self::Sample(Arc::new(sample))
+            }
+        })
+    }
+}
+
+impl From<&Effect> for EffectFile {
+    fn from(value: &Effect) -> Self {
+        match *value {
+            Effect::LowPass { cutoff, resonance } => Self::LowPass { cutoff, resonance },
+            Effect::Delay {
+                time,
+                feedback,
+                mix,
+            } => Self::Delay {
+                time,
+                feedback,
+                mix,
+            },
+            Effect::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => Self::Reverb {
+                room_size,
+                damping,
+                mix,
+            },
+        }
+    }
+}
+
+impl TryFrom<EffectFile> for Effect {
+    type Error = ProjectError;
+
+    fn try_from(value: EffectFile) -> Result<Self, Self::Error> {
+        Ok(match value {
+            EffectFile::LowPass { cutoff, resonance } => Self::LowPass {
+                cutoff: within(cutoff, FINITE, "filter cutoff")?,
+                resonance: within(resonance, FINITE, "filter resonance")?,
+            },
+            EffectFile::Delay {
+                time,
+                feedback,
+                mix,
+            } => {
+                if time > effects::MAX_DELAY {
+                    return Err(ProjectError::Invalid(format!(
+                        "delay time of {} ms is too long",
+                        time.as_millis()
+                    )));
+                }
+                Self::Delay {
+                    time,
+                    feedback: within(feedback, FINITE, "delay feedback")?,
+                    mix: within(mix, FINITE, "delay mix")?,
+                }
+            }
+            EffectFile::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => Self::Reverb {
+                room_size: within(room_size, FINITE, "reverb room size")?,
+                damping: within(damping, FINITE, "reverb damping")?,
+                mix: within(mix, FINITE, "reverb mix")?,
+            },
+        })
+    }
 }
@@ -0,0 +1,405 @@
+//! A plain-text score language.
+//!
+//! ```text
+//! # Comments run to the end of the line.
+//! tempo 96
+//! time 3/4
+//! Ah/4 ChyLy/8. Chy/16 r/4     # chords by name, durations as note values, "r" rests
+//! Ah/2~ Ah/8 Ly+               # "~" ties into the same tone, a missing duration repeats the last one
+//! { Ah/2 Chy/4 | Ly-/2. }      # voices played at the same time, separated by "|"
+//! ( Chy/8 Ly/8 )*4             # repeats, as long as the score stays within about a million whole notes
+//! base 440                     # unison in Hz from here on
+//! base Chy                     # or moved by a harmonym, here up a fifth
+//! ramp 140 /1                  # tempo change spread over a whole note
+//! ```
+
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use nom::{
+    IResult, Parser,
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::complete::{char, digit1, multispace1, space1},
+    combinator::{cut, map, map_res, opt, rest_len, verify},
+    multi::{many0, many0_count, separated_list1},
+    number::complete::double,
+    sequence::{delimited, preceded},
+};
+
+use crate::{
+    chord::Chord,
+    note::{Harmonym, parse_harmonym},
+    score::{NoteDuration, Score, ScoreNote},
+    time::{MAX_BEATS, TempoMap, Ticks, TimeSignature, note_length},
+};
+
+#[derive(Debug)]
+pub enum DslError {
+    Io(io::Error),
+    /// The text stopped making sense at `line` and `column`, both counted from 1.
+    Syntax {
+        line: usize,
+        column: usize,
+        found: String,
+    },
+    /// The item at `line` and `column` runs past the longest score that can be laid out, e.g. through repeats.
+    TooLong {
+        line: usize,
+        column: usize,
+    },
+}
+
+/// Most items laid out for one score, counting each pass through a repeat, so nested repeats cannot hang the parser.
+const MAX_ITEMS: usize = 1 << 20;
+
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read score: {}", e),
+            Self::Syntax {
+                line,
+                column,
+                found,
+            } => write!(
+                f,
+                "expected a chord, rest or command at line {}, column {}, found \"{}\"",
+                line, column, found
+            ),
+            Self::TooLong { line, column } => {
+                write!(f, "score runs too long at line {}, column {}", line, column)
+            }
+        }
+    }
+}
+
+impl Error for DslError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DslError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Base {
+    Hz(f32),
+    /// Moves the unison by a harmonym.
+    Relative(Harmonym),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Chord {
+        tones: Vec<Harmonym>,
+        length: Option<Ticks>,
+        /// Tones carry on into the same tones of the next chord.
+        tie: bool,
+    },
+    Rest(Option<Ticks>),
+    Base(Base),
+    Tempo(f64),
+    Ramp(f64, Ticks),
+    Time(TimeSignature),
+    Parallel(Vec<Vec<Placed>>),
+    Repeat(Vec<Placed>, u32),
+}
+
+/// An item with the length of the input left where it starts, to point errors at it.
+type Placed = (usize, Item);
+
+/// Whitespace and comments.
+fn blank(input: &str) -> IResult<&str, ()> {
+    map(
+        many0_count(alt((
+            multispace1,
+            preceded(char('#'), take_till(|el| el == '\n')),
+        ))),
+        |_| (),
+    )
+    .parse(input)
+}
+
+fn number(input: &str) -> IResult<&str, u32> {
+    map_res(digit1, str::parse).parse(input)
+}
+
+/// A note value like `/4` for a quarter, with a dot adding half of the value and each further dot half as much again.
+fn length(input: &str) -> IResult<&str, Ticks> {
+    map(
+        (
+            preceded(char('/'), verify(number, |el| (1..=256).contains(el))),
+            many0_count(char('.')),
+        ),
+        |(denominator, dots)| {
+            let value = note_length(1, denominator);
+            (0..dots.min(4)).fold(value, |acc, el| acc + (value >> (el + 1)))
+        },
+    )
+    .parse(input)
+}
+
+fn chord(input: &str) -> IResult<&str, Item> {
+    let name = verify(
+        take_while1(|el: char| el.is_ascii_alphabetic() || el == '+' || el == '-'),
+        |el: &str| el.starts_with(|first: char| first.is_ascii_uppercase()),
+    );
+    map(
+        (
+            map_res(name, |el| Chord::parse_chord(el).map(|(_, chord)| chord)),
+            opt(length),
+            opt(char('~')),
+        ),
+        |(chord, length, tie)| Item::Chord {
+            tones: chord.tones,
+            length,
+            tie: tie.is_some(),
+        },
+    )
+    .parse(input)
+}
+
+fn rest(input: &str) -> IResult<&str, Item> {
+    map(preceded(char('r'), opt(length)), Item::Rest).parse(input)
+}
+
+fn command(input: &str) -> IResult<&str, Item> {
+    let harmonym = map_res(
+        take_while1(|el: char| el.is_ascii_alphabetic() || el == '+' || el == '-'),
+        |el| parse_harmonym(el).map(|(_, harmonym)| harmonym),
+    );
+    alt((
+        preceded(
+            (tag("base"), space1),
+            alt((
+                map(harmonym, |el| Item::Base(Base::Relative(el))),
+                map(verify(double, |el| *el > 0.), |el| {
+                    Item::Base(Base::Hz(el as f32))
+                }),
+            )),
+        ),
+        preceded(
+            (tag("tempo"), space1),
+            map(verify(double, |el| *el > 0.), Item::Tempo),
+        ),
+        map(
+            preceded(
+                (tag("ramp"), space1),
+                (verify(double, |el| *el > 0.), preceded(space1, length)),
+            ),
+            |(bpm, length)| Item::Ramp(bpm, length),
+        ),
+        map(
+            preceded(
+                (tag("time"), space1),
+                (
+                    verify(number, |el| (1..=MAX_BEATS).contains(el)),
+                    preceded(char('/'), verify(number, |el| (1..=256).contains(el))),
+                ),
+            ),
+            |(beats, unit)| Item::Time(TimeSignature { beats, unit }),
+        ),
+    ))
+    .parse(input)
+}
+
+fn items(input: &str) -> IResult<&str, Vec<Placed>> {
+    many0(preceded(blank, (rest_len, item))).parse(input)
+}
+
+fn item(input: &str) -> IResult<&str, Item> {
+    alt((
+        command,
+        map(
+            (
+                delimited(char('('), items, preceded(blank, cut(char(')')))),
+                cut(preceded(char('*'), number)),
+            ),
+            |(body, times)| Item::Repeat(body, times),
+        ),
+        map(
+            delimited(
+                char('{'),
+                separated_list1(preceded(blank, char('|')), items),
+                preceded(blank, cut(char('}'))),
+            ),
+            Item::Parallel,
+        ),
+        chord,
+        rest,
+    ))
+    .parse(input)
+}
+
+/// Where one voice is while the items are laid out.
+#[derive(Clone)]
+struct Voice {
+    at: Ticks,
+    /// Length of the last chord or rest, used when the next one leaves it out.
+    length: Ticks,
+    base: Option<f32>,
+    /// Notes the previous chord tied over, by tone.
+    ties: Vec<(Harmonym, usize)>,
+}
+
+struct Layout {
+    notes: Vec<ScoreNote>,
+    tempo: TempoMap,
+    base: f32,
+    /// Items that can still be laid out, see [`MAX_ITEMS`].
+    budget: usize,
+}
+
+impl Layout {
+    /// Returns the position of the item that ran too long, as the length of the input left there.
+    fn place(&mut self, items: &[Placed], voice: &mut Voice) -> Result<(), usize> {
+        for (position, item) in items {
+            let too_long = || *position;
+            self.budget = self.budget.checked_sub(1).ok_or_else(too_long)?;
+            match item {
+                Item::Chord { tones, length, tie } => {
+                    let length = length.unwrap_or(voice.length);
+                    let mut ties = Vec::new();
+                    for tone in tones {
+                        let tied = voice.ties.iter().find(|el| el.0 == *tone).map(|el| el.1);
+                        let idx = match tied {
+                            Some(idx) => {
+                                let note = &mut self.notes[idx].duration.length;
+                                *note = note.checked_add(length).ok_or_else(too_long)?;
+                                idx
+                            }
+                            None => {
+                                let mut note: ScoreNote = (
+                                    NoteDuration {
+                                        start: voice.at,
+                                        length,
+                                    },
+                                    *tone,
+                                )
+                                    .into();
+                                note.base = voice.base;
+                                self.notes.push(note);
+                                self.notes.len() - 1
+                            }
+                        };
+                        if *tie {
+                            ties.push((*tone, idx));
+                        }
+                    }
+                    voice.ties = ties;
+                    voice.length = length;
+                    voice.at = voice.at.checked_add(length).ok_or_else(too_long)?;
+                }
+                Item::Rest(length) => {
+                    voice.length = length.unwrap_or(voice.length);
+                    voice.ties.clear();
+                    voice.at = voice.at.checked_add(voice.length).ok_or_else(too_long)?;
+                }
+                Item::Base(Base::Hz(hz)) => voice.base = Some(*hz),
+                Item::Base(Base::Relative(harmonym)) => {
+                    voice.base = Some(*harmonym * voice.base.unwrap_or(self.base));
+                }
+                Item::Tempo(bpm) => self.tempo.set_tempo(voice.at, *bpm),
+                Item::Ramp(bpm, length) => {
+                    let end = voice.at.checked_add(*length).ok_or_else(too_long)?;
+                    self.tempo.ramp_tempo(voice.at, end, *bpm);
+                }
+                Item::Time(signature) => {
+                    // Takes effect on the bar that starts here, or else on the next one.
+                    let position = self.tempo.position(voice.at);
+                    let bar = if position.beat == 1 && position.tick == 0 {
+                        position.bar
+                    } else {
+                        position.bar + 1
+                    };
+                    self.tempo.set_time_signature(bar, *signature);
+                }
+                Item::Parallel(voices) => {
+                    let mut end = voice.at;
+                    for items in voices {
+                        let mut branch = voice.clone();
+                        self.place(items, &mut branch)?;
+                        end = end.max(branch.at);
+                    }
+                    voice.at = end;
+                    voice.ties.clear();
+                }
+                Item::Repeat(body, times) => {
+                    for _ in 0..*times {
+                        self.place(body, voice)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Line and column, both counted from 1, where `rest` starts in `input`.
+fn locate(input: &str, rest: usize) -> (usize, usize) {
+    let offset = input.len() - rest;
+    let line_start = input[..offset].rfind('\n').map_or(0, |el| el + 1);
+    (
+        input[..offset].matches('\n').count() + 1,
+        input[line_start..offset].chars().count() + 1,
+    )
+}
+
+/// Builds a score from text in the score language.
+pub fn parse(input: &str) -> Result<Score, DslError> {
+    let (rest, items) = match (items, blank).parse(input) {
+        Ok((rest, (items, _))) => (rest, items),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => (e.input, Vec::new()),
+        Err(nom::Err::Incomplete(_)) => (input, Vec::new()),
+    };
+    if !rest.is_empty() {
+        let (line, column) = locate(input, rest.len());
+        return Err(DslError::Syntax {
+            line,
+            column,
+            found: rest
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+        });
+    }
+
+    let mut score = Score::new();
+    let mut layout = Layout {
+        notes: Vec::new(),
+        tempo: TempoMap::default(),
+        base: score.base(),
+        budget: MAX_ITEMS,
+    };
+    layout
+        .place(
+            &items,
+            &mut Voice {
+                at: 0,
+                length: note_length(1, 4),
+                base: None,
+                ties: Vec::new(),
+            },
+        )
+        .map_err(|rest| {
+            let (line, column) = locate(input, rest);
+            DslError::TooLong { line, column }
+        })?;
+    score.set_tempo(layout.tempo);
+    for note in layout.notes {
+        score.push(note);
+    }
+    Ok(score)
+}
+
+/// Reads and parses a score file.
+pub fn load(path: impl AsRef<Path>) -> Result<Score, DslError> {
+    parse(&fs::read_to_string(path)?)
+}
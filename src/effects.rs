@@ -0,0 +1,440 @@
+use std::{f32::consts::PI, time::Duration};
+
+use rodio::Source;
+
+/// A source boxed so effects can be chained at runtime.
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Below this level the tail of a delay or reverb counts as silent.
+const SILENCE: f32 = 1e-4;
+
+/// Longest delay time. Longer ones are shortened to it, so the delay line stays a few MB at most.
+pub const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Settings of one effect in a chain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Resonant low-pass filter. `resonance` is the Q, 0.707 for a flat response.
+    LowPass { cutoff: f32, resonance: f32 },
+    /// Echo repeated every `time`, each repeat `feedback` times as loud as the last.
+    Delay {
+        time: Duration,
+        feedback: f32,
+        mix: f32,
+    },
+    /// Algorithmic reverb. `room_size` and `damping` range from 0 to 1.
+    Reverb {
+        room_size: f32,
+        damping: f32,
+        mix: f32,
+    },
+}
+
+impl Effect {
+    /// Wraps `source` in this effect.
+    pub fn apply(&self, source: BoxedSource) -> BoxedSource {
+        match *self {
+            Self::LowPass { cutoff, resonance } => {
+                Box::new(LowPass::new(source, cutoff, resonance))
+            }
+            Self::Delay {
+                time,
+                feedback,
+                mix,
+            } => Box::new(Delay::new(source, time, feedback, mix)),
+            Self::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => Box::new(Reverb::new(source, room_size, damping, mix)),
+        }
+    }
+}
+
+/// Runs `source` through every effect of `effects` in order.
+pub fn chain(source: impl Source<Item = f32> + Send + 'static, effects: &[Effect]) -> BoxedSource {
+    effects
+        .iter()
+        .fold(Box::new(source), |source, effect| effect.apply(source))
+}
+
+/// Tracks which channel of an interleaved source the next sample belongs to.
+#[derive(Debug, Clone, Copy)]
+struct Interleave {
+    channels: usize,
+    channel: usize,
+}
+
+impl Interleave {
+    fn new(channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            channel: 0,
+        }
+    }
+
+    /// Channel of the next sample. The channel count is only picked up again at the start of a frame.
+    fn next(&mut self, channels: u16) -> usize {
+        if self.channel == 0 {
+            self.channels = channels.max(1) as usize;
+        }
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.channels;
+        channel
+    }
+}
+
+/// Topology-preserving state-variable low-pass filter, one per channel.
+pub struct LowPass<S> {
+    source: S,
+    cutoff: f32,
+    resonance: f32,
+    interleave: Interleave,
+    /// Sample rate the coefficients were computed for.
+    sample_rate: u32,
+    coefficients: [f32; 3],
+    /// Integrator states of each channel.
+    states: Vec<[f32; 2]>,
+}
+
+impl<S: Source<Item = f32>> LowPass<S> {
+    pub fn new(source: S, cutoff: f32, resonance: f32) -> Self {
+        Self {
+            interleave: Interleave::new(source.channels()),
+            source,
+            cutoff,
+            resonance: resonance.max(0.5),
+            sample_rate: 0,
+            coefficients: [0.; 3],
+            states: Vec::new(),
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        self.sample_rate = self.source.sample_rate();
+        let nyquist = self.sample_rate as f32 / 2.;
+        let g = (PI * self.cutoff.clamp(1., nyquist * 0.99) / self.sample_rate as f32).tan();
+        let k = 1. / self.resonance;
+        let a1 = 1. / (1. + g * (g + k));
+        self.coefficients = [a1, g * a1, g * g * a1];
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for LowPass<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let input = self.source.next()?;
+        if self.sample_rate != self.source.sample_rate() {
+            self.update_coefficients();
+        }
+        let channel = self.interleave.next(self.source.channels());
+        if self.states.len() <= channel {
+            self.states.resize(channel + 1, [0.; 2]);
+        }
+        let [a1, a2, a3] = self.coefficients;
+        let [ic1, ic2] = &mut self.states[channel];
+        let v3 = input - *ic2;
+        let v1 = a1 * *ic1 + a2 * v3;
+        let v2 = *ic2 + a2 * *ic1 + a3 * v3;
+        *ic1 = 2. * v1 - *ic1;
+        *ic2 = 2. * v2 - *ic2;
+        Some(v2)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for LowPass<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Feedback delay that keeps echoing after its source has ended.
+pub struct Delay<S> {
+    source: S,
+    time: Duration,
+    feedback: f32,
+    mix: f32,
+    /// Interleaved delay line, allocated once the sample rate and channel count are known.
+    buffer: Vec<f32>,
+    position: usize,
+    /// Channel count and sample rate of the source, kept for the tail once it has ended.
+    channels: u16,
+    sample_rate: u32,
+    ended: bool,
+    /// Samples in a row that were below [`SILENCE`] since the source ended.
+    silent: usize,
+}
+
+impl<S: Source<Item = f32>> Delay<S> {
+    pub fn new(source: S, time: Duration, feedback: f32, mix: f32) -> Self {
+        Self {
+            channels: source.channels(),
+            sample_rate: source.sample_rate(),
+            source,
+            time: time.min(MAX_DELAY),
+            // Any more and the echoes would grow forever.
+            feedback: feedback.clamp(0., 0.99),
+            mix: mix.clamp(0., 1.),
+            buffer: Vec::new(),
+            position: 0,
+            ended: false,
+            silent: 0,
+        }
+    }
+
+    /// Whether a whole pass through the delay line has been silent since the source ended. Only checked at the start of
+    /// a frame so the output never ends halfway through one.
+    fn is_silent(&self) -> bool {
+        self.ended
+            && self.silent > self.buffer.len()
+            && self.position.is_multiple_of(self.channels.max(1) as usize)
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Delay<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_silent() {
+            return None;
+        }
+        let input = if self.ended {
+            0.
+        } else {
+            match self.source.next() {
+                Some(o) => o,
+                None => {
+                    self.ended = true;
+                    0.
+                }
+            }
+        };
+        if self.buffer.is_empty() {
+            let frames = (self.time.as_secs_f64() * self.sample_rate as f64).max(1.) as usize;
+            self.buffer = vec![0.; frames * self.channels.max(1) as usize];
+        }
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        let out = input * (1. - self.mix) + delayed * self.mix;
+        if self.ended {
+            self.silent = if out.abs() < SILENCE {
+                self.silent + 1
+            } else {
+                0
+            };
+        }
+        Some(out)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Delay<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Comb filter lengths of Freeverb at 44.1 kHz.
+const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// All-pass filter lengths of Freeverb at 44.1 kHz.
+const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+/// Extra length per channel, which decorrelates the channels and widens the reverb.
+const STEREO_SPREAD: usize = 23;
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.;
+
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    /// State of the low-pass in the feedback path.
+    filtered: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.buffer[self.position];
+        self.filtered = out * (1. - damping) + self.filtered * damping;
+        self.buffer[self.position] = input + self.filtered * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        out
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * 0.5;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// Parallel combs into serial all-passes for a single channel.
+struct ReverbChannel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl ReverbChannel {
+    fn new(channel: usize, sample_rate: u32) -> Self {
+        let scale = |length: usize| {
+            (((length + channel * STEREO_SPREAD) as f64 * sample_rate as f64 / 44100.) as usize)
+                .max(1)
+        };
+        Self {
+            combs: COMBS
+                .iter()
+                .map(|el| Comb {
+                    buffer: vec![0.; scale(*el)],
+                    position: 0,
+                    filtered: 0.,
+                })
+                .collect(),
+            allpasses: ALLPASSES
+                .iter()
+                .map(|el| Allpass {
+                    buffer: vec![0.; scale(*el)],
+                    position: 0,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Freeverb-style reverb that rings out after its source has ended.
+pub struct Reverb<S> {
+    source: S,
+    feedback: f32,
+    damping: f32,
+    mix: f32,
+    reverbs: Vec<ReverbChannel>,
+    interleave: Interleave,
+    channels: u16,
+    sample_rate: u32,
+    ended: bool,
+    /// Samples in a row that were below [`SILENCE`] since the source ended.
+    silent: usize,
+}
+
+impl<S: Source<Item = f32>> Reverb<S> {
+    pub fn new(source: S, room_size: f32, damping: f32, mix: f32) -> Self {
+        Self {
+            channels: source.channels(),
+            sample_rate: source.sample_rate(),
+            interleave: Interleave::new(source.channels()),
+            source,
+            feedback: 0.7 + 0.28 * room_size.clamp(0., 1.),
+            damping: 0.4 * damping.clamp(0., 1.),
+            mix: mix.clamp(0., 1.),
+            reverbs: Vec::new(),
+            ended: false,
+            silent: 0,
+        }
+    }
+
+    /// Whether the tail has been silent for longer than the longest comb, which is as long as the combs can hold
+    /// energy. Only checked at the start of a frame so the output never ends halfway through one.
+    fn is_silent(&self) -> bool {
+        let longest = self
+            .reverbs
+            .iter()
+            .flat_map(|el| &el.combs)
+            .map(|el| el.buffer.len())
+            .max();
+        self.ended
+            && self.silent > longest.unwrap_or(0) * self.channels.max(1) as usize
+            && self.interleave.channel == 0
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Reverb<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_silent() {
+            return None;
+        }
+        let input = if self.ended {
+            0.
+        } else {
+            match self.source.next() {
+                Some(o) => o,
+                None => {
+                    self.ended = true;
+                    0.
+                }
+            }
+        };
+        let channel = self.interleave.next(self.channels);
+        while self.reverbs.len() <= channel {
+            self.reverbs
+                .push(ReverbChannel::new(self.reverbs.len(), self.sample_rate));
+        }
+        let reverb = &mut self.reverbs[channel];
+        let mut wet: f32 = reverb
+            .combs
+            .iter_mut()
+            .map(|el| el.process(input * INPUT_GAIN, self.feedback, self.damping))
+            .sum();
+        for allpass in &mut reverb.allpasses {
+            wet = allpass.process(wet);
+        }
+        let out = input * (1. - self.mix) + wet * WET_GAIN * self.mix;
+        if self.ended {
+            self.silent = if out.abs() < SILENCE {
+                self.silent + 1
+            } else {
+                0
+            };
+        }
+        Some(out)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Reverb<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
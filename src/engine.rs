@@ -0,0 +1,451 @@
+use std::{
+    sync::mpsc::{Receiver, SendError, Sender, TryRecvError, channel},
+    time::Duration,
+};
+
+use rodio::Source;
+
+use crate::{
+    DEFAULT_BASE,
+    envelope::{Adsr, Envelope},
+    glide::{Glide, Gliding, Portamento},
+    instrument::{Generator, Instrument},
+    mix::{Meter, Mixer, pan},
+    modulation::{Modulation, Modulator, voice_seed},
+    note::Harmonym,
+    playable::PlayOptions,
+};
+
+/// Voices the engine plays at once before it starts stealing.
+pub const DEFAULT_POLYPHONY: usize = 32;
+/// How long a stolen voice takes to fade out, short enough to free it quickly but long enough not to click.
+const STEAL_FADE: Duration = Duration::from_millis(5);
+
+/// A note to start on an [`Engine`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub harmonym: Harmonym,
+    /// Stereo position from -1 (left) to 1 (right).
+    pub pan: f32,
+    /// Time to wait before the note sounds, e.g. for strumming.
+    pub delay: Duration,
+    /// Sends its own note-off after sounding for this long. Held until [`Event::NoteOff`] otherwise.
+    pub hold: Option<Duration>,
+}
+
+impl From<Harmonym> for Note {
+    fn from(value: Harmonym) -> Self {
+        Self {
+            harmonym: value,
+            pan: 0.,
+            delay: Duration::ZERO,
+            hold: None,
+        }
+    }
+}
+
+/// Message sent to a running [`Engine`].
+#[derive(Debug, Clone)]
+pub enum Event {
+    NoteOn(Note),
+    /// Releases every held voice of the harmonym.
+    NoteOff(Harmonym),
+    /// Moves every voice playing `from` along `glide`. The voices answer to the harmonym they glide to from then on.
+    Glide {
+        from: Harmonym,
+        glide: Glide,
+    },
+    AllNotesOff,
+    /// Instrument of the voices started from now on.
+    SetInstrument(Instrument),
+    /// Envelope of the voices started from now on.
+    SetEnvelope(Adsr),
+    /// Frequency in Hz of the unison harmonym for the voices started from now on.
+    SetBase(f32),
+    /// With a portamento, each new note takes over the closest held voice and glides it to its pitch.
+    SetPortamento(Option<Portamento>),
+    /// Vibrato and tremolo of the voices started from now on.
+    SetModulation(Modulation),
+    /// Releases the held voices, lets the ones with a `hold` play on, and ends the engine once all of them have rung
+    /// out. Later notes are ignored.
+    Finish,
+}
+
+struct Voice {
+    harmonym: Harmonym,
+    /// Frequency of the unison harmonym when the voice started.
+    base: f32,
+    /// Frequency ratio to the unison the voice is sounding, before vibrato.
+    pitch: f32,
+    generator: Generator,
+    glide: Option<Gliding>,
+    modulator: Modulator,
+    envelope: Envelope,
+    pan: f32,
+    /// Frames left before the voice sounds.
+    delay: u32,
+    /// Frames left before the voice releases itself.
+    hold: Option<u32>,
+    /// Frame the voice was started on, used to steal the oldest.
+    started: u64,
+    /// Gain of a stolen voice on its way out.
+    fade: Option<f32>,
+}
+
+impl Voice {
+    /// Pitch the voice is sounding in cents above the unison, before vibrato.
+    fn cents(&self) -> f32 {
+        1200. * self.pitch.log2()
+    }
+}
+
+/// A stereo source that plays any number of overlapping notes, driven by [`Event`]s from an [`EngineHandle`].
+///
+/// Runs until it receives [`Event::Finish`] or every handle has been dropped. Notes fade through their release when
+/// they end, and when all voices are busy the quietest released voice, or else the oldest one, is faded out quickly
+/// to make room.
+pub struct Engine {
+    receiver: Receiver<Event>,
+    sample_rate: u32,
+    polyphony: usize,
+    base: f32,
+    instrument: Instrument,
+    envelope: Adsr,
+    portamento: Option<Portamento>,
+    modulation: Modulation,
+    voices: Vec<Voice>,
+    frame: u64,
+    finishing: bool,
+    mixer: Mixer,
+    /// Right channel of the current frame, returned after the left one.
+    pending_right: Option<f32>,
+}
+
+impl Engine {
+    /// Panics if `sample_rate` is 0.
+    pub fn new(sample_rate: u32, polyphony: usize) -> (Self, EngineHandle) {
+        assert!(sample_rate > 0, "sample rate must not be 0");
+        let (sender, receiver) = channel();
+        let mixer = Mixer::new(sample_rate);
+        let instrument = Instrument::default();
+        instrument.prefetch(sample_rate);
+        let handle = EngineHandle {
+            sender,
+            sample_rate,
+            meter: mixer.meter(),
+        };
+        let engine = Self {
+            receiver,
+            sample_rate,
+            polyphony: polyphony.max(1),
+            base: DEFAULT_BASE,
+            instrument,
+            envelope: Adsr::default(),
+            portamento: None,
+            modulation: Modulation::default(),
+            voices: Vec::new(),
+            frame: 0,
+            finishing: false,
+            mixer,
+            pending_right: None,
+        };
+        (engine, handle)
+    }
+
+    fn frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * self.sample_rate as f64) as u32
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::NoteOn(note) => self.note_on(note),
+            Event::NoteOff(harmonym) => self
+                .voices
+                .iter_mut()
+                .filter(|el| el.harmonym == harmonym)
+                .for_each(|el| el.envelope.note_off()),
+            Event::Glide { from, glide } => {
+                for voice in self.voices.iter_mut().filter(|el| el.harmonym == from) {
+                    voice.glide = Some(
+                        Gliding::new(&glide, from, self.sample_rate).starting_at(voice.cents()),
+                    );
+                    voice.harmonym = glide.to;
+                }
+            }
+            Event::AllNotesOff => self.voices.iter_mut().for_each(|el| el.envelope.note_off()),
+            Event::SetInstrument(instrument) => self.instrument = instrument,
+            Event::SetEnvelope(envelope) => self.envelope = envelope,
+            Event::SetBase(base) => self.base = base,
+            Event::SetPortamento(portamento) => self.portamento = portamento,
+            Event::SetModulation(modulation) => self.modulation = modulation,
+            Event::Finish => {
+                self.finishing = true;
+                self.voices
+                    .iter_mut()
+                    .filter(|el| el.hold.is_none())
+                    .for_each(|el| el.envelope.note_off());
+            }
+        }
+    }
+
+    fn note_on(&mut self, note: Note) {
+        if self.finishing {
+            return;
+        }
+        if let Some(portamento) = self.portamento
+            && self.glide_in(note, portamento)
+        {
+            return;
+        }
+        let active = self.voices.iter().filter(|el| el.fade.is_none()).count();
+        if active >= self.polyphony {
+            self.steal();
+        }
+        // The frame keeps repeated notes of the same chord from sharing a phase or a pluck.
+        let seed = voice_seed(note.harmonym, self.voices.len()) ^ self.frame;
+        self.voices.push(Voice {
+            harmonym: note.harmonym,
+            base: self.base,
+            pitch: note.harmonym * 1.,
+            glide: None,
+            generator: self
+                .instrument
+                .generator(note.harmonym * self.base, self.sample_rate, seed),
+            modulator: Modulator::new(&self.modulation, seed, self.sample_rate),
+            envelope: Envelope::new(self.envelope, self.sample_rate),
+            pan: note.pan,
+            delay: self.frames(note.delay),
+            hold: note.hold.map(|el| self.frames(el)),
+            started: self.frame,
+            fade: None,
+        });
+    }
+
+    /// Glides the held voice closest to `note` over to it. Returns `false` if there is no voice to take over.
+    fn glide_in(&mut self, note: Note, portamento: Portamento) -> bool {
+        let frame = self.frame;
+        let sample_rate = self.sample_rate;
+        // The voice is sounding already, so its hold starts counting now rather than after the delay.
+        let hold = note.hold.map(|el| self.frames(el + note.delay));
+        let target = note.harmonym.cents();
+        // Voices started on this frame are notes of the same chord, so they are left alone.
+        let Some(voice) = self
+            .voices
+            .iter_mut()
+            .filter(|el| !el.envelope.is_released() && el.fade.is_none() && el.started != frame)
+            .min_by(|a, b| {
+                (a.harmonym.cents() - target)
+                    .abs()
+                    .total_cmp(&(b.harmonym.cents() - target).abs())
+            })
+        else {
+            return false;
+        };
+        let glide = Glide {
+            delay: note.delay,
+            ..portamento.glide(note.harmonym)
+        };
+        // A voice still gliding moves on from where it has got to rather than jumping to its last target first.
+        voice.glide =
+            Some(Gliding::new(&glide, voice.harmonym, sample_rate).starting_at(voice.cents()));
+        voice.harmonym = note.harmonym;
+        voice.pan = note.pan;
+        voice.hold = hold;
+        voice.started = frame;
+        true
+    }
+
+    /// Starts fading out the voice that will be missed least.
+    fn steal(&mut self) {
+        let victim = self
+            .voices
+            .iter_mut()
+            .filter(|el| el.fade.is_none())
+            .min_by(|a, b| {
+                // Released voices go first, quietest first, then the oldest held voice.
+                b.envelope
+                    .is_released()
+                    .cmp(&a.envelope.is_released())
+                    .then(if a.envelope.is_released() {
+                        a.envelope.level().total_cmp(&b.envelope.level())
+                    } else {
+                        a.started.cmp(&b.started)
+                    })
+            });
+        if let Some(victim) = victim {
+            victim.fade = Some(1.);
+        }
+    }
+}
+
+impl Iterator for Engine {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => self.handle(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.finishing {
+                        self.handle(Event::Finish);
+                    }
+                    break;
+                }
+            }
+        }
+        let fade_step = 1. / self.frames(STEAL_FADE).max(1) as f32;
+        self.voices
+            .retain(|el| !el.envelope.is_finished() && el.fade.is_none_or(|fade| fade > 0.));
+        if self.finishing && self.voices.is_empty() {
+            return None;
+        }
+
+        let mut total = [0.; 2];
+        for voice in &mut self.voices {
+            if voice.delay > 0 {
+                voice.delay -= 1;
+                continue;
+            }
+            let (vibrato, gain) = voice.modulator.next().unwrap();
+            if let Some(glide) = &mut voice.glide {
+                match glide.next() {
+                    Some(ratio) => {
+                        voice.pitch = ratio;
+                        voice.generator.set_frequency(ratio * voice.base * vibrato);
+                    }
+                    None => voice.glide = None,
+                }
+            } else if voice.modulator.has_vibrato() {
+                voice
+                    .generator
+                    .set_frequency(voice.pitch * voice.base * vibrato);
+            }
+            match &mut voice.hold {
+                Some(0) => voice.envelope.note_off(),
+                Some(frames) => *frames -= 1,
+                None => (),
+            }
+            let mut sample =
+                voice.generator.next().unwrap() * voice.envelope.next().unwrap() * gain;
+            if let Some(fade) = &mut voice.fade {
+                sample *= *fade;
+                *fade -= fade_step;
+            }
+            let [left, right] = pan(sample, voice.pan);
+            total[0] += left;
+            total[1] += right;
+        }
+        self.frame += 1;
+        let [left, right] = self.mixer.mix(total, self.voices.len());
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for Engine {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Sends events to an [`Engine`] from any thread. Every method fails once the engine has been dropped.
+#[derive(Debug, Clone)]
+pub struct EngineHandle {
+    sender: Sender<Event>,
+    sample_rate: u32,
+    meter: Meter,
+}
+
+impl EngineHandle {
+    pub fn send(&self, event: Event) -> Result<(), SendError<Event>> {
+        self.sender.send(event)
+    }
+
+    pub fn note_on(&self, note: impl Into<Note>) -> Result<(), SendError<Event>> {
+        self.send(Event::NoteOn(note.into()))
+    }
+
+    pub fn note_off(&self, harmonym: Harmonym) -> Result<(), SendError<Event>> {
+        self.send(Event::NoteOff(harmonym))
+    }
+
+    pub fn glide(&self, from: Harmonym, glide: Glide) -> Result<(), SendError<Event>> {
+        self.send(Event::Glide { from, glide })
+    }
+
+    pub fn set_portamento(&self, portamento: Option<Portamento>) -> Result<(), SendError<Event>> {
+        self.send(Event::SetPortamento(portamento))
+    }
+
+    pub fn set_modulation(&self, modulation: Modulation) -> Result<(), SendError<Event>> {
+        self.send(Event::SetModulation(modulation))
+    }
+
+    pub fn all_notes_off(&self) -> Result<(), SendError<Event>> {
+        self.send(Event::AllNotesOff)
+    }
+
+    pub fn set_instrument(
+        &self,
+        instrument: impl Into<Instrument>,
+    ) -> Result<(), SendError<Event>> {
+        let instrument = instrument.into();
+        instrument.prefetch(self.sample_rate);
+        self.send(Event::SetInstrument(instrument))
+    }
+
+    pub fn set_envelope(&self, envelope: Adsr) -> Result<(), SendError<Event>> {
+        self.send(Event::SetEnvelope(envelope))
+    }
+
+    pub fn set_base(&self, base: f32) -> Result<(), SendError<Event>> {
+        self.send(Event::SetBase(base))
+    }
+
+    pub fn finish(&self) -> Result<(), SendError<Event>> {
+        self.send(Event::Finish)
+    }
+
+    /// Starts every tone of a chord, strummed and spread as set in `options`. With a `hold`, the chord releases
+    /// itself after it.
+    pub fn chord(
+        &self,
+        harmonyms: &[Harmonym],
+        options: &PlayOptions,
+        hold: Option<Duration>,
+    ) -> Result<(), SendError<Event>> {
+        let starts = options.starts(harmonyms, self.sample_rate);
+        let pans = options.spread.pans(harmonyms);
+        for ((harmonym, start), pan) in harmonyms.iter().zip(starts).zip(pans) {
+            let delay = Duration::from_secs_f64(start as f64 / self.sample_rate as f64);
+            self.note_on(Note {
+                harmonym: *harmonym,
+                pan,
+                delay,
+                hold,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Output level of the engine, updated while it plays.
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+}
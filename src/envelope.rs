@@ -0,0 +1,152 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+/// Attack, decay, sustain and release settings of a voice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack: Duration,
+    pub decay: Duration,
+    /// Level held after the decay, between 0 and 1.
+    pub sustain: f32,
+    pub release: Duration,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self {
+            attack: Duration::from_millis(10),
+            decay: Duration::from_millis(150),
+            sustain: 0.7,
+            release: Duration::from_millis(300),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Finished,
+}
+
+/// Running state of an [`Adsr`] for a single voice. Yields one gain value per frame.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    adsr: Adsr,
+    sample_rate: u32,
+    stage: Stage,
+    level: f32,
+    /// Level at the moment of note-off, which the release ramps down from.
+    released_from: f32,
+    position: u32,
+}
+
+impl Envelope {
+    pub fn new(adsr: Adsr, sample_rate: u32) -> Self {
+        Self {
+            adsr: Adsr {
+                sustain: if adsr.sustain.is_nan() {
+                    0.
+                } else {
+                    adsr.sustain.clamp(0., 1.)
+                },
+                ..adsr
+            },
+            sample_rate,
+            stage: Stage::Attack,
+            level: 0.,
+            released_from: 0.,
+            position: 0,
+        }
+    }
+
+    /// Starts the release stage from the current level.
+    pub fn note_off(&mut self) {
+        if matches!(self.stage, Stage::Release | Stage::Finished) {
+            return;
+        }
+        self.stage = Stage::Release;
+        self.released_from = self.level;
+        self.position = 0;
+    }
+
+    /// Whether the release has run out and the voice is silent for good.
+    pub fn is_finished(&self) -> bool {
+        self.stage == Stage::Finished
+    }
+
+    /// Gain of the last frame.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Whether [`Envelope::note_off`] has been called.
+    pub fn is_released(&self) -> bool {
+        matches!(self.stage, Stage::Release | Stage::Finished)
+    }
+
+    fn frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * self.sample_rate as f64) as u32
+    }
+}
+
+impl Iterator for Envelope {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (length, from, to, next) = match self.stage {
+                Stage::Attack => (self.frames(self.adsr.attack), 0., 1., Stage::Decay),
+                Stage::Decay => (
+                    self.frames(self.adsr.decay),
+                    1.,
+                    self.adsr.sustain,
+                    Stage::Sustain,
+                ),
+                Stage::Sustain => {
+                    self.level = self.adsr.sustain;
+                    return Some(self.level);
+                }
+                Stage::Release => (
+                    self.frames(self.adsr.release),
+                    self.released_from,
+                    0.,
+                    Stage::Finished,
+                ),
+                Stage::Finished => {
+                    self.level = 0.;
+                    return Some(0.);
+                }
+            };
+            if self.position >= length {
+                self.stage = next;
+                self.position = 0;
+                continue;
+            }
+            self.level = from + (to - from) * self.position as f32 / length as f32;
+            self.position += 1;
+            return Some(self.level);
+        }
+    }
+}
+
+/// Sends a note-off to a playing source from another thread, so its voices fade out through their release.
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseHandle(Arc<AtomicBool>);
+
+impl ReleaseHandle {
+    pub fn release(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use crate::note::Harmonym;
+
+/// Route a [`Glide`] takes through pitch space.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GlidePath {
+    /// A straight line in log frequency.
+    #[default]
+    Direct,
+    /// Through the lattice points on the way, one step along one dimension at a time. Each step glides over
+    /// `portamento` (0 to 1) of its share of the time and holds the new point for the rest.
+    Lattice { portamento: f32 },
+}
+
+/// A pitch movement from the harmonym a voice is playing to another one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glide {
+    pub to: Harmonym,
+    /// Time the voice holds its pitch before starting to move.
+    pub delay: Duration,
+    pub duration: Duration,
+    pub path: GlidePath,
+}
+
+impl Glide {
+    /// A direct glide to `to` over `duration`, starting at once.
+    pub fn new(to: Harmonym, duration: Duration) -> Self {
+        Self {
+            to,
+            delay: Duration::ZERO,
+            duration,
+            path: GlidePath::Direct,
+        }
+    }
+
+    /// The harmonyms the glide passes through on its way from `from`, both ends included.
+    pub fn points(&self, from: Harmonym) -> Vec<Harmonym> {
+        let GlidePath::Lattice { .. } = self.path else {
+            return vec![from, self.to];
+        };
+        let start = from.exponents().map(i32::from);
+        let distance: [i32; 5] =
+            std::array::from_fn(|idx| i32::from(self.to.exponents()[idx]) - start[idx]);
+        let total: i32 = distance.iter().map(|el| el.abs()).sum();
+        let mut taken = [0i32; 5];
+        let mut points = vec![from];
+        let mut current = from.exponents();
+        for _ in 0..total {
+            // Step along the dimension that is furthest behind its share, which keeps the path close to the straight
+            // line between the two points.
+            let Some(dimension) =
+                (0..5)
+                    .filter(|el| taken[*el] < distance[*el].abs())
+                    .min_by(|a, b| {
+                        let progress = |el: usize| taken[el] as f32 / distance[el].abs() as f32;
+                        progress(*a).total_cmp(&progress(*b))
+                    })
+            else {
+                break;
+            };
+            taken[dimension] += 1;
+            current[dimension] += distance[dimension].signum() as i8;
+            points.extend(Harmonym::from_exponents(current));
+        }
+        points
+    }
+}
+
+/// How a new note glides in from the nearest held one instead of starting on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Portamento {
+    pub duration: Duration,
+    pub path: GlidePath,
+}
+
+impl Portamento {
+    /// The glide from a held note to `to`.
+    pub fn glide(&self, to: Harmonym) -> Glide {
+        Glide {
+            to,
+            delay: Duration::ZERO,
+            duration: self.duration,
+            path: self.path,
+        }
+    }
+}
+
+/// A [`Glide`] in progress, yielding the frequency ratio to the unison for each frame until it arrives.
+#[derive(Debug, Clone)]
+pub struct Gliding {
+    /// Pitch of every point on the path in cents.
+    cents: Vec<f32>,
+    portamento: f32,
+    delay: u32,
+    frames: u32,
+    frame: u32,
+}
+
+impl Gliding {
+    pub fn new(glide: &Glide, from: Harmonym, sample_rate: u32) -> Self {
+        let frames = |duration: Duration| (duration.as_secs_f64() * sample_rate as f64) as u32;
+        Self {
+            cents: glide.points(from).iter().map(Harmonym::cents).collect(),
+            portamento: match glide.path {
+                GlidePath::Direct => 1.,
+                GlidePath::Lattice { portamento } => portamento.clamp(0., 1.),
+            },
+            delay: frames(glide.delay),
+            frames: frames(glide.duration).max(1),
+            frame: 0,
+        }
+    }
+
+    /// Starts the path at `cents` instead of at the harmonym it leaves, e.g. from wherever an interrupted glide had
+    /// got to.
+    pub fn starting_at(mut self, cents: f32) -> Self {
+        self.cents[0] = cents;
+        self
+    }
+
+    /// Pitch in cents at `progress` (0 to 1) along the path.
+    fn cents_at(&self, progress: f32) -> f32 {
+        let segments = self.cents.len() - 1;
+        if segments == 0 {
+            return self.cents[0];
+        }
+        let position = progress * segments as f32;
+        let segment = (position as usize).min(segments - 1);
+        let local = position - segment as f32;
+        let moved = if self.portamento > 0. {
+            (local / self.portamento).min(1.)
+        } else {
+            1.
+        };
+        self.cents[segment] + (self.cents[segment + 1] - self.cents[segment]) * moved
+    }
+}
+
+impl Iterator for Gliding {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let progress = if self.delay > 0 {
+            self.delay -= 1;
+            0.
+        } else if self.frame < self.frames {
+            self.frame += 1;
+            self.frame as f32 / self.frames as f32
+        } else {
+            return None;
+        };
+        Some((self.cents_at(progress) / 1200.).exp2())
+    }
+}
@@ -0,0 +1,674 @@
+use std::{
+    error::Error,
+    f32::consts::PI,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    modulation::split_mix,
+    note::Harmonym,
+    oscillator::Oscillator,
+    playable::{Bandlimit, DEFAULT_WAVE, Waveform},
+    resample::{Quality, kernel},
+    wav, wavetable,
+};
+
+/// What a voice sounds like.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instrument {
+    Wave {
+        waveform: Waveform,
+        bandlimit: Bandlimit,
+    },
+    Additive(Timbre),
+    Fm(Fm),
+    /// A plucked string by Karplus-Strong synthesis.
+    Pluck(Pluck),
+    /// A recording resampled to the pitch of each voice.
+    Sample(Arc<Sample>),
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        DEFAULT_WAVE.into()
+    }
+}
+
+impl From<Waveform> for Instrument {
+    fn from(value: Waveform) -> Self {
+        Self::Wave {
+            waveform: value,
+            bandlimit: Bandlimit::default(),
+        }
+    }
+}
+
+impl From<Timbre> for Instrument {
+    fn from(value: Timbre) -> Self {
+        Self::Additive(value)
+    }
+}
+
+impl From<Fm> for Instrument {
+    fn from(value: Fm) -> Self {
+        Self::Fm(value)
+    }
+}
+
+impl From<Pluck> for Instrument {
+    fn from(value: Pluck) -> Self {
+        Self::Pluck(value)
+    }
+}
+
+impl From<Sample> for Instrument {
+    fn from(value: Sample) -> Self {
+        Self::Sample(Arc::new(value))
+    }
+}
+
+impl Instrument {
+    /// Builds the wavetables this instrument reads, if they are not cached yet, so its first voice does not build them
+    /// on the audio thread.
+    pub fn prefetch(&self, sample_rate: u32) {
+        if let Self::Wave {
+            waveform,
+            bandlimit: Bandlimit::Additive,
+        } = self
+        {
+            wavetable::ladder(*waveform, sample_rate);
+        }
+    }
+
+    /// Starts a voice of this instrument at `frequency` Hz. Instruments that play noise draw it from `seed`, see
+    /// [`voice_seed`](crate::modulation::voice_seed).
+    pub fn generator(&self, frequency: f32, sample_rate: u32, seed: u64) -> Generator {
+        match self {
+            Self::Wave {
+                waveform,
+                bandlimit,
+            } => Generator::Wave(Oscillator::new(
+                frequency,
+                *waveform,
+                *bandlimit,
+                sample_rate,
+            )),
+            Self::Additive(timbre) => {
+                Generator::Additive(AdditiveVoice::new(timbre, frequency, sample_rate))
+            }
+            Self::Fm(fm) => Generator::Fm(FmVoice::new(*fm, frequency, sample_rate)),
+            Self::Pluck(pluck) => {
+                Generator::Pluck(PluckVoice::new(*pluck, frequency, sample_rate, seed))
+            }
+            Self::Sample(sample) => {
+                Generator::Sample(SampleVoice::new(sample.clone(), frequency, sample_rate))
+            }
+        }
+    }
+}
+
+/// A running voice of an [`Instrument`].
+pub enum Generator {
+    Wave(Oscillator),
+    Additive(AdditiveVoice),
+    Fm(FmVoice),
+    Pluck(PluckVoice),
+    Sample(SampleVoice),
+}
+
+impl Generator {
+    /// Moves the voice to `frequency` Hz without restarting it, e.g. for a glide.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        match self {
+            Self::Wave(el) => el.set_frequency(frequency),
+            Self::Additive(el) => el.set_frequency(frequency),
+            Self::Fm(el) => el.set_frequency(frequency),
+            Self::Pluck(el) => el.set_frequency(frequency),
+            Self::Sample(el) => el.set_frequency(frequency),
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Wave(el) => el.next(),
+            Self::Additive(el) => el.next(),
+            Self::Fm(el) => el.next(),
+            Self::Pluck(el) => el.next(),
+            Self::Sample(el) => el.next(),
+        }
+    }
+}
+
+/// A partial of a [`Timbre`], relative to the fundamental.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Partial {
+    /// Frequency as a multiple of the fundamental. Need not be a whole number.
+    pub ratio: f32,
+    pub amplitude: f32,
+}
+
+impl From<(f32, f32)> for Partial {
+    fn from(value: (f32, f32)) -> Self {
+        Self {
+            ratio: value.0,
+            amplitude: value.1,
+        }
+    }
+}
+
+/// A spectrum rendered by additive synthesis, one sine per partial.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Timbre {
+    pub partials: Vec<Partial>,
+}
+
+impl From<Vec<Partial>> for Timbre {
+    fn from(value: Vec<Partial>) -> Self {
+        Self { partials: value }
+    }
+}
+
+impl Timbre {
+    /// Harmonic spectrum where `amplitudes[n]` is the amplitude of harmonic `n + 1`.
+    pub fn harmonic(amplitudes: &[f32]) -> Self {
+        amplitudes
+            .iter()
+            .enumerate()
+            .map(|(n, amplitude)| Partial {
+                ratio: (n + 1) as f32,
+                amplitude: *amplitude,
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Spectrum whose partials land on the other tones of `harmonyms`, after Sethares.
+    ///
+    /// Every upward interval between two tones becomes a partial, repeated over `octaves` octaves,
+    /// so any tone of the chord played with this timbre shares partials with the others.
+    pub fn matched(harmonyms: &[Harmonym], octaves: u32) -> Self {
+        let mut ratios: Vec<f32> = Vec::new();
+        for low in harmonyms {
+            for high in harmonyms {
+                let interval = high.cents() - low.cents();
+                if interval < 0. {
+                    continue;
+                }
+                for octave in 0..=octaves {
+                    ratios.push(2f32.powf(interval / 1200.) * 2u32.pow(octave) as f32);
+                }
+            }
+        }
+        ratios.sort_by(f32::total_cmp);
+        ratios.dedup_by(|a, b| (*a / *b - 1.).abs() < 1e-4);
+        ratios
+            .into_iter()
+            .map(|ratio| Partial {
+                ratio,
+                amplitude: 1. / ratio,
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Sine partials of a [`Timbre`] summed together, normalised so the peak stays within 1.
+pub struct AdditiveVoice {
+    /// Phase from 0 to 1, frequency ratio and amplitude of each partial.
+    partials: Vec<(f32, f32, f32)>,
+    /// Phase increment per frame of the fundamental.
+    increment: f32,
+    sample_rate: u32,
+}
+
+impl AdditiveVoice {
+    pub fn new(timbre: &Timbre, frequency: f32, sample_rate: u32) -> Self {
+        let total: f32 = timbre.partials.iter().map(|el| el.amplitude.abs()).sum();
+        // Without a single audible partial there is nothing to normalise, so the voice stays silent.
+        let partials = if total.is_normal() {
+            timbre
+                .partials
+                .iter()
+                .map(|el| (0., el.ratio, el.amplitude / total))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            partials,
+            increment: frequency / sample_rate as f32,
+            sample_rate,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.increment = frequency / self.sample_rate as f32;
+    }
+}
+
+impl Iterator for AdditiveVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut total = 0.;
+        for (phase, ratio, amplitude) in &mut self.partials {
+            let increment = *ratio * self.increment;
+            // Partials above Nyquist would alias, so they stay silent until a glide brings them back down.
+            if increment < 0.5 {
+                total += *amplitude * (2. * PI * *phase).sin();
+            }
+            *phase = (*phase + increment).fract();
+        }
+        Some(total)
+    }
+}
+
+/// Two-operator frequency modulation: a sine modulator, optionally fed back into itself, bending the phase of a sine
+/// carrier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fm {
+    /// Frequency of the modulator as a multiple of the carrier. Whole numbers give harmonic spectra.
+    pub ratio: f32,
+    /// Peak phase deviation of the carrier in radians. Higher values give more and louder sidebands.
+    pub index: f32,
+    /// Amount of its own output fed back into the modulator, from 0 to 1, which pushes it towards a saw.
+    pub feedback: f32,
+}
+
+impl Default for Fm {
+    fn default() -> Self {
+        Self {
+            ratio: 1.,
+            index: 2.,
+            feedback: 0.,
+        }
+    }
+}
+
+/// A running [`Fm`] voice.
+pub struct FmVoice {
+    fm: Fm,
+    /// Phases from 0 to 1.
+    carrier: f32,
+    modulator: f32,
+    /// Carrier phase increment per frame.
+    increment: f32,
+    /// Index lowered so the sidebands stay below Nyquist.
+    index: f32,
+    /// Last output of the modulator, for the feedback.
+    previous: f32,
+    sample_rate: u32,
+}
+
+impl FmVoice {
+    pub fn new(fm: Fm, frequency: f32, sample_rate: u32) -> Self {
+        let mut voice = Self {
+            fm,
+            carrier: 0.,
+            modulator: 0.,
+            increment: 0.,
+            index: 0.,
+            previous: 0.,
+            sample_rate,
+        };
+        voice.set_frequency(frequency);
+        voice
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.increment = frequency / self.sample_rate as f32;
+        // Carson's rule puts nearly all the energy within (index + 1) modulator frequencies of the carrier, so the
+        // index is capped to keep that band below Nyquist.
+        let limit =
+            (0.5 - self.increment) / (self.fm.ratio.abs() * self.increment).max(f32::EPSILON) - 1.;
+        self.index = self.fm.index.min(limit).max(0.);
+    }
+}
+
+impl Iterator for FmVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let feedback = self.fm.feedback.clamp(0., 1.) * self.previous;
+        // Averaging with the last value keeps strong feedback from oscillating between two states.
+        let modulator = (2. * PI * self.modulator + feedback).sin();
+        self.previous = (self.previous + modulator) / 2.;
+        let out = (2. * PI * self.carrier + self.index * modulator).sin();
+        self.carrier = (self.carrier + self.increment).fract();
+        self.modulator = (self.modulator + self.increment * self.fm.ratio).rem_euclid(1.);
+        Some(out)
+    }
+}
+
+/// A plucked string: a burst of noise circulating in a delay line one period long, losing its high frequencies a
+/// little more on every pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pluck {
+    /// Time the string takes to fade by 60 dB.
+    pub decay: f32,
+    /// Brightness of the pluck from 0 (soft, thumb-like) to 1 (sharp, pick-like).
+    pub brightness: f32,
+}
+
+impl Default for Pluck {
+    fn default() -> Self {
+        Self {
+            decay: 2.,
+            brightness: 0.7,
+        }
+    }
+}
+
+/// Lowest pitch a [`PluckVoice`] reaches, which sets the length of its delay line.
+const LOWEST_PLUCK: f32 = 20.;
+
+/// A running [`Pluck`] voice.
+///
+/// The loop delay is the delay line plus the delay of the loss filter plus a fractional allpass, which tunes the
+/// string exactly instead of to the nearest whole period.
+pub struct PluckVoice {
+    pluck: Pluck,
+    /// Ring buffer holding at least one period.
+    line: Vec<f32>,
+    write: usize,
+    /// Whole frames of the loop delay spent in the line.
+    delay: usize,
+    /// Weight of the previous sample in the loss filter, at most 0.5. Lower values keep more of the high frequencies.
+    stretch: f32,
+    /// Coefficient and state of the allpass making up the fractional rest.
+    allpass: f32,
+    allpass_in: f32,
+    allpass_out: f32,
+    /// Previous sample read from the line, for the loss filter.
+    previous: f32,
+    /// Gain on each pass through the loop.
+    gain: f32,
+    sample_rate: u32,
+}
+
+impl PluckVoice {
+    /// Plucks a string at `frequency` Hz with a noise burst drawn from `seed`, so repeated notes differ.
+    pub fn new(pluck: Pluck, frequency: f32, sample_rate: u32, seed: u64) -> Self {
+        let length = (sample_rate as f32 / LOWEST_PLUCK).ceil() as usize + 2;
+        let mut voice = Self {
+            pluck,
+            line: vec![0.; length],
+            write: 0,
+            delay: 1,
+            stretch: 0.5,
+            allpass: 0.,
+            allpass_in: 0.,
+            allpass_out: 0.,
+            previous: 0.,
+            gain: 1.,
+            sample_rate,
+        };
+        voice.set_frequency(frequency);
+        voice.excite(split_mix(seed));
+        voice
+    }
+
+    /// Fills the period about to be read with noise, smoothed according to the brightness.
+    fn excite(&mut self, seed: u64) {
+        let mut state = seed | 1;
+        let mut noise = || {
+            // Xorshift, enough for an excitation burst.
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 23) as f32 - 1.
+        };
+        let smoothing = self.pluck.brightness.clamp(0.05, 1.);
+        let mut burst: Vec<f32> = Vec::with_capacity(self.delay);
+        let mut smoothed = 0.;
+        for _ in 0..self.delay {
+            smoothed += smoothing * (noise() - smoothed);
+            burst.push(smoothed);
+        }
+        // Without its DC offset the burst cannot leave the string settling on a constant.
+        let mean = burst.iter().sum::<f32>() / burst.len().max(1) as f32;
+        let peak = burst
+            .iter()
+            .fold(0f32, |acc, el| acc.max((el - mean).abs()));
+        let length = self.line.len();
+        for (idx, el) in burst.into_iter().enumerate() {
+            self.line[(length - self.delay + idx) % length] = (el - mean) / peak.max(1e-6);
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        let frequency = frequency.max(LOWEST_PLUCK);
+        let period = self.sample_rate as f32 / frequency;
+        let omega = 2. * PI / period;
+        // Loss per pass that fades the fundamental by 60 dB over the decay time.
+        let target = 0.001f32.powf(1. / (frequency * self.pluck.decay.max(1e-3)));
+        // A plain two-point average loses so much per pass that high strings die at once. Weighting it less, after
+        // Jaffe and Smith, takes off only the loss the decay asks for, and the gain makes up the rest on low strings.
+        let average = (omega / 2.).cos();
+        if average >= target {
+            self.stretch = 0.5;
+            self.gain = target / average;
+        } else {
+            let product = (1. - target * target) / (2. * (1. - omega.cos()));
+            self.stretch = (1. - (1. - 4. * product).max(0.).sqrt()) / 2.;
+            self.gain = 1.;
+        }
+        // Phase delay of the loss filter at the fundamental, which the line and the allpass make up to a period.
+        let filter = (self.stretch * omega.sin())
+            .atan2(1. - self.stretch + self.stretch * omega.cos())
+            / omega;
+        // Keeping the allpass delay between 0.1 and 1.1 frames keeps its coefficient away from -1.
+        let whole = (period - filter - 0.1).floor().max(1.);
+        let fraction = period - filter - whole;
+        self.delay = (whole as usize).min(self.line.len() - 1);
+        self.allpass = ((1. - fraction) * omega / 2.).sin() / ((1. + fraction) * omega / 2.).sin();
+    }
+}
+
+impl Iterator for PluckVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = self.line.len();
+        let out = self.line[(self.write + length - self.delay) % length];
+        let averaged = ((1. - self.stretch) * out + self.stretch * self.previous) * self.gain;
+        self.previous = out;
+        let shifted = self.allpass * averaged + self.allpass_in - self.allpass * self.allpass_out;
+        self.allpass_in = averaged;
+        self.allpass_out = shifted;
+        self.line[self.write] = shifted;
+        self.write = (self.write + 1) % length;
+        Some(out)
+    }
+}
+
+#[derive(Debug)]
+pub enum SampleError {
+    Wav(hound::Error),
+    Empty,
+    /// The loop points are reversed or lie outside the recording.
+    InvalidLoop,
+    /// The pitch of the recording is not a positive frequency.
+    InvalidRoot(f32),
+    /// The recording claims a sample rate of 0.
+    ZeroSampleRate,
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wav(e) => write!(f, "failed to read wav file: {}", e),
+            Self::Empty => write!(f, "sample contains no audio"),
+            Self::InvalidLoop => write!(f, "loop points lie outside the sample"),
+            Self::InvalidRoot(root) => write!(f, "invalid root frequency of {} Hz", root),
+            Self::ZeroSampleRate => write!(f, "sample has a sample rate of 0"),
+        }
+    }
+}
+
+impl Error for SampleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Wav(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<hound::Error> for SampleError {
+    fn from(value: hound::Error) -> Self {
+        Self::Wav(value)
+    }
+}
+
+/// A mono recording of a single pitch.
+#[derive(Clone, PartialEq)]
+pub struct Sample {
+    data: Vec<f32>,
+    sample_rate: u32,
+    /// Frequency of the recorded pitch in Hz.
+    root: f32,
+    /// Start and end frame of the sustain loop.
+    looping: Option<(usize, usize)>,
+    /// File the recording was loaded from.
+    path: Option<PathBuf>,
+}
+
+impl fmt::Debug for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sample")
+            .field("frames", &self.data.len())
+            .field("sample_rate", &self.sample_rate)
+            .field("root", &self.root)
+            .field("looping", &self.looping)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Sample {
+    pub fn new(data: Vec<f32>, sample_rate: u32, root: f32) -> Result<Self, SampleError> {
+        if data.is_empty() {
+            return Err(SampleError::Empty);
+        }
+        if !(root.is_finite() && root > 0.) {
+            return Err(SampleError::InvalidRoot(root));
+        }
+        if sample_rate == 0 {
+            return Err(SampleError::ZeroSampleRate);
+        }
+        Ok(Self {
+            data,
+            sample_rate,
+            root,
+            looping: None,
+            path: None,
+        })
+    }
+
+    /// Loads a WAV file whose pitch is `root` Hz. Multichannel files are mixed down.
+    pub fn load(path: impl AsRef<Path>, root: f32) -> Result<Self, SampleError> {
+        let (data, sample_rate) = wav::read_mono(&path)?;
+        let mut sample = Self::new(data, sample_rate, root)?;
+        sample.path = Some(path.as_ref().to_owned());
+        Ok(sample)
+    }
+
+    pub fn root(&self) -> f32 {
+        self.root
+    }
+
+    pub fn looping(&self) -> Option<(usize, usize)> {
+        self.looping
+    }
+
+    /// File the recording was loaded from, if it was loaded from one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Repeats the frames from `start` up to `end` for as long as the note is held.
+    pub fn set_loop(&mut self, start: usize, end: usize) -> Result<(), SampleError> {
+        if start >= end || end > self.data.len() {
+            return Err(SampleError::InvalidLoop);
+        }
+        self.looping = Some((start, end));
+        Ok(())
+    }
+
+    /// Frame `idx`, following the loop past its end and silent outside the recording.
+    fn frame(&self, idx: isize) -> f32 {
+        let idx = match self.looping {
+            Some((start, end)) if idx >= end as isize => {
+                start + (idx as usize - start) % (end - start)
+            }
+            _ if idx < 0 => return 0.,
+            _ => idx as usize,
+        };
+        self.data.get(idx).copied().unwrap_or(0.)
+    }
+}
+
+/// How many times wider than at the original pitch the interpolation kernel of a [`SampleVoice`] can grow.
+const MAX_KERNEL_SPREAD: f64 = 16.;
+
+/// Plays a [`Sample`] at another pitch with windowed-sinc interpolation.
+pub struct SampleVoice {
+    sample: Arc<Sample>,
+    /// Read position in frames of the sample.
+    position: f64,
+    /// Frames of the sample advanced per output frame.
+    step: f64,
+    sample_rate: u32,
+}
+
+impl SampleVoice {
+    pub fn new(sample: Arc<Sample>, frequency: f32, sample_rate: u32) -> Self {
+        Self {
+            step: Self::step(&sample, frequency, sample_rate),
+            sample,
+            position: 0.,
+            sample_rate,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.step = Self::step(&self.sample, frequency, self.sample_rate);
+    }
+
+    fn step(sample: &Sample, frequency: f32, sample_rate: u32) -> f64 {
+        (frequency / sample.root) as f64 * sample.sample_rate as f64 / sample_rate as f64
+    }
+}
+
+impl Iterator for SampleVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((start, end)) = self.sample.looping
+            && self.position >= end as f64
+        {
+            self.position -= (end - start) as f64;
+        }
+        // Lower the cutoff when reading faster than real time so the transposition does not alias.
+        let cutoff = (1. / self.step).min(1.);
+        let zeros = Quality::Standard.zero_crossings() as f64;
+        // Capped so a voice pitched far above the recording stays affordable, at the price of a softer cutoff.
+        let width = (zeros / cutoff).ceil().min(zeros * MAX_KERNEL_SPREAD) as isize;
+        let center = self.position.floor() as isize;
+        let frac = self.position - center as f64;
+        let mut total = 0.;
+        for offset in 1 - width..=width {
+            total += self.sample.frame(center + offset) as f64
+                * kernel(offset as f64 - frac, cutoff, width as f64);
+        }
+        self.position += self.step;
+        Some(total as f32)
+    }
+}
@@ -0,0 +1,211 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    DEFAULT_BASE,
+    note::{Harmonym, MAX_DEGREE},
+};
+
+/// A bounded region of the harmonym lattice.
+///
+/// Every harmonym inside `bounds` is visited; the optional limits then filter it further.
+#[derive(Debug, Clone)]
+pub struct Region {
+    /// Exponent bounds per dimension, ordered from dimension 1 (octave) to 5. Bounds beyond
+    /// [`MAX_DEGREE`] are clamped, since nothing there has a name.
+    pub bounds: [RangeInclusive<i8>; 5],
+    pub max_tenney_height: Option<f32>,
+    pub odd_limit: Option<u64>,
+    /// Range of the interval above the unison, in cents.
+    pub cents: Option<RangeInclusive<f32>>,
+}
+
+impl Default for Region {
+    /// Every harmonym that has a name, without further limits.
+    fn default() -> Self {
+        Self {
+            bounds: std::array::from_fn(|_| -MAX_DEGREE..=MAX_DEGREE),
+            max_tenney_height: None,
+            odd_limit: None,
+            cents: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Lowest pitch first.
+    Pitch,
+    /// Lowest Tenney height first, ties broken by pitch.
+    Complexity,
+}
+
+impl Region {
+    pub fn contains(&self, harmonym: &Harmonym) -> bool {
+        harmonym
+            .exponents()
+            .iter()
+            .zip(&self.bounds)
+            .all(|(exp, bound)| bound.contains(exp))
+            && self
+                .max_tenney_height
+                .is_none_or(|max| harmonym.tenney_height() <= max)
+            && self.odd_limit.is_none_or(|max| harmonym.odd_limit() <= max)
+            && self
+                .cents
+                .as_ref()
+                .is_none_or(|range| range.contains(&harmonym.cents()))
+    }
+
+    pub fn iter(&self) -> LatticeIter<'_> {
+        let bounds = self
+            .bounds
+            .clone()
+            .map(|el| (*el.start()).max(-MAX_DEGREE)..=(*el.end()).min(MAX_DEGREE));
+        LatticeIter {
+            region: self,
+            next: bounds
+                .iter()
+                .all(|el| !el.is_empty())
+                .then(|| bounds.clone().map(|el| *el.start())),
+            bounds,
+        }
+    }
+
+    /// Collects the region, ordered by `order`.
+    pub fn sorted(&self, order: Order) -> Vec<Harmonym> {
+        let mut ret: Vec<Harmonym> = self.iter().collect();
+        match order {
+            Order::Pitch => ret.sort_by(|a, b| a.cents().total_cmp(&b.cents())),
+            Order::Complexity => ret.sort_by(|a, b| {
+                a.tenney_height()
+                    .total_cmp(&b.tenney_height())
+                    .then_with(|| a.cents().total_cmp(&b.cents()))
+            }),
+        }
+        ret
+    }
+
+    /// Finds every other harmonym in the region within `tolerance` cents of `target`.
+    ///
+    /// Results are ordered by the size of the comma, then by complexity. Use `max_tenney_height` to bound the search.
+    pub fn near_equivalents(&self, target: Harmonym, tolerance: f32) -> Vec<NearEquivalent> {
+        let mut ret: Vec<NearEquivalent> = self
+            .iter()
+            .filter(|el| *el != target)
+            .map(|el| NearEquivalent {
+                harmonym: el,
+                comma: el.checked_div(target),
+                cents: el.cents() - target.cents(),
+            })
+            .filter(|el| el.cents.abs() <= tolerance)
+            .collect();
+        ret.sort_by(|a, b| {
+            a.cents.abs().total_cmp(&b.cents.abs()).then_with(|| {
+                a.harmonym
+                    .tenney_height()
+                    .total_cmp(&b.harmonym.tenney_height())
+            })
+        });
+        ret
+    }
+}
+
+impl Region {
+    /// Ranks the harmonyms of the region by how well they fit `frequency` Hz above `base`.
+    ///
+    /// Only candidates within `max_deviation` cents are kept. See [`FrequencyMatch::score`] for the ranking.
+    pub fn lookup(&self, frequency: f32, base: f32, max_deviation: f32) -> Vec<FrequencyMatch> {
+        let measured = 1200. * (frequency / base).log2();
+        let mut ret: Vec<FrequencyMatch> = self
+            .iter()
+            .map(|el| FrequencyMatch {
+                harmonym: el,
+                deviation: measured - el.cents(),
+                complexity: el.tenney_height(),
+            })
+            .filter(|el| el.deviation.abs() <= max_deviation)
+            .collect();
+        ret.sort_by(|a, b| a.score().total_cmp(&b.score()));
+        ret
+    }
+}
+
+/// Looks up `frequency` against [`DEFAULT_BASE`] among all named harmonyms, within a quarter tone.
+pub fn lookup_frequency(frequency: f32) -> Vec<FrequencyMatch> {
+    Region::default().lookup(frequency, DEFAULT_BASE, 50.)
+}
+
+/// A candidate harmonym for a measured frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyMatch {
+    pub harmonym: Harmonym,
+    /// Measured pitch minus the pitch of `harmonym`, in cents.
+    pub deviation: f32,
+    /// Tenney height of `harmonym`.
+    pub complexity: f32,
+}
+
+impl FrequencyMatch {
+    /// Ranking score, lower is better. One bit of Tenney height weighs as much as one cent of deviation.
+    pub fn score(&self) -> f32 {
+        self.deviation.abs() + self.complexity
+    }
+}
+
+/// A harmonym lying within a small tolerance of another one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearEquivalent {
+    pub harmonym: Harmonym,
+    /// The comma from the reference harmonym up to `harmonym`, or `None` if it is too wide to have a name, as most
+    /// commas are when a dimension reaches 4 steps.
+    pub comma: Option<Harmonym>,
+    /// Size of the comma in cents, negative if `harmonym` is lower.
+    pub cents: f32,
+}
+
+impl<'a> IntoIterator for &'a Region {
+    type Item = Harmonym;
+    type IntoIter = LatticeIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Walks the exponent bounds of a [`Region`] like an odometer, yielding the harmonyms that pass its limits.
+pub struct LatticeIter<'a> {
+    region: &'a Region,
+    bounds: [RangeInclusive<i8>; 5],
+    next: Option<[i8; 5]>,
+}
+
+impl LatticeIter<'_> {
+    fn advance(&mut self) {
+        let Some(current) = self.next.as_mut() else {
+            return;
+        };
+        for (exp, bound) in current.iter_mut().zip(&self.bounds).rev() {
+            if *exp < *bound.end() {
+                *exp += 1;
+                return;
+            }
+            *exp = *bound.start();
+        }
+        self.next = None;
+    }
+}
+
+impl Iterator for LatticeIter<'_> {
+    type Item = Harmonym;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let exponents = self.next?;
+            self.advance();
+            if let Some(harmonym) =
+                Harmonym::from_exponents(exponents).filter(|el| self.region.contains(el))
+            {
+                return Some(harmonym);
+            }
+        }
+    }
+}
@@ -1,6 +1,23 @@
+pub mod backend;
 pub mod chord;
+pub mod data;
+pub mod dsl;
+pub mod effects;
+pub mod engine;
+pub mod envelope;
+pub mod glide;
+pub mod instrument;
+pub mod lattice;
+pub mod mix;
+pub mod modulation;
 pub mod note;
+pub mod oscillator;
 pub mod playable;
+pub mod render;
+pub mod resample;
 pub mod score;
+pub mod time;
+pub mod transcribe;
+pub mod wav;
+pub mod wavetable;
 pub const DEFAULT_BASE: f32 = 523.26;
-pub static STRUMMING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
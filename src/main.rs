@@ -1,38 +1,92 @@
+#[cfg(feature = "cli")]
 use std::{
-    io::{BufRead, stdin},
-    sync::atomic::AtomicBool,
+    io::{self, BufRead, stdin},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
     time::Duration,
 };
 
-use rodio::{OutputStream, Sink, Source};
-
-use crate::{chord::Chord, playable::PlayableChord};
+use chalaxata_rs::backend::RodioBackend;
+#[cfg(feature = "cli")]
+use chalaxata_rs::{
+    backend::{AudioBackend, BackendError, NullBackend, WavBackend},
+    chord::Chord,
+    data::{AUTOSAVE_INTERVAL, Autosave, EditorState, SessionData, Track},
+    dsl,
+    effects::{self, Effect},
+    engine::{DEFAULT_POLYPHONY, Engine, EngineHandle},
+    envelope::Adsr,
+    glide::{GlidePath, Portamento},
+    instrument::{Fm, Instrument, Partial, Pluck, Sample, SampleError, Timbre},
+    modulation::Lfo,
+    note,
+    playable::{
+        Bandlimit, DEFAULT_WAVE, PlayOptions, SAMPLE_RATE, Spread, Strum, StrumDirection, Waveform,
+    },
+    render::BlockRenderer,
+    resample::Quality,
+    transcribe, wavetable,
+};
 
-mod chord;
-mod data;
+#[cfg(not(feature = "cli"))]
 mod gui;
-mod note;
-mod playable;
-mod score;
-
-pub const DEFAULT_BASE: f32 = 523.26;
-pub static STRUMMING: AtomicBool = AtomicBool::new(false);
 
-#[cfg(target_feature = "cli")]
+#[cfg(feature = "cli")]
 fn main() {
     let mut stack = false;
-    let (_stream, handle) = OutputStream::try_default().unwrap();
+    let mut backend = select_backend();
     let mut lines = stdin().lock().lines();
-    let mut sinks = Vec::new();
+    let shared = Arc::new(Mutex::new(
+        offer_recovery(&mut lines, None).unwrap_or_default(),
+    ));
+    let editor = lock(&shared).project().editor.clone();
+    let mut envelope = editor.envelope;
+    let mut instrument = editor.instrument;
+    let mut match_timbre = false;
+    let mut options = PlayOptions::default();
+    let mut chain: Vec<Effect> = editor.effects;
+    let mut portamento = editor.portamento;
+    let mut modulation = editor.modulation;
     let mut harmonyms: Vec<note::Harmonym> = Vec::new();
-    while let Some(Ok(i)) = lines.next() {
-        let sink = Sink::try_new(&handle).unwrap();
+    let mut engine = match start_engine(backend.as_mut(), &chain) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let _autosave = Autosave::start(shared.clone(), AUTOSAVE_INTERVAL, |e| {
+        println!("Failed to autosave: {}", e)
+    });
+    let mut exiting = false;
+    // Whether the session ended with "exit" rather than at the end of the input.
+    let exited = loop {
+        {
+            // The settings are saved with the project, so they are kept up to date for autosaving while waiting for
+            // the next command.
+            let mut session = lock(&shared);
+            let editor = EditorState {
+                instrument: instrument.clone(),
+                envelope,
+                modulation,
+                portamento,
+                effects: chain.clone(),
+                ..session.project().editor.clone()
+            };
+            if editor != session.project().editor {
+                session.project_mut().editor = editor;
+            }
+        }
+        let Some(Ok(i)) = lines.next() else {
+            break false;
+        };
+        let mut session = lock(&shared);
         match i.as_str() {
             "stack" => {
                 if stack {
                     println!("Stopped stacking");
                     stack = false;
-                    sinks.clear();
+                    engine.all_notes_off().ok();
                     harmonyms.clear();
                 } else {
                     println!("Stacking");
@@ -41,9 +95,32 @@ fn main() {
             }
             "stop" => {
                 println!("All sounds stopped");
-                sinks.clear();
+                engine.all_notes_off().ok();
                 harmonyms.clear();
             }
+            "level" => {
+                let meter = engine.meter();
+                println!(
+                    "peak {:>6.1} dBFS, rms {:>6.1} dBFS",
+                    meter.peak_db(),
+                    meter.rms_db()
+                );
+                meter.reset();
+            }
+            "cache" => {
+                let stats = wavetable::stats();
+                println!(
+                    "{} wavetables of {} waveforms cached ({} of {} KiB), {} hits, {} misses ({:.0}% hit rate), {} evicted.",
+                    stats.tables,
+                    stats.ladders,
+                    stats.bytes() / 1024,
+                    stats.capacity / 1024,
+                    stats.hits,
+                    stats.misses,
+                    stats.hit_rate() * 100.,
+                    stats.evictions
+                );
+            }
             "gather" => {
                 let mut chordname: String = String::new();
                 for i in &harmonyms {
@@ -54,13 +131,182 @@ fn main() {
             "strum" => {
                 println!(
                     "{}",
-                    if STRUMMING.fetch_not(std::sync::atomic::Ordering::Release) {
+                    if options.strum.take().is_some() {
                         "Stopped strumming."
                     } else {
+                        options.strum = Some(Strum::default());
                         "Strumming."
                     }
                 )
             }
+            cmd if cmd.starts_with("strum ") => {
+                let mut strum = options.strum.unwrap_or_default();
+                let mut numbers = Vec::new();
+                for arg in cmd.split_whitespace().skip(1) {
+                    match arg {
+                        "up" => strum.direction = StrumDirection::Up,
+                        "down" => strum.direction = StrumDirection::Down,
+                        "alternating" => strum.direction = StrumDirection::Alternating,
+                        other => match other.parse::<f32>() {
+                            Ok(o) if o > 0. && o.is_finite() => numbers.push(o),
+                            _ => println!("Ignoring invalid strum setting: {}", other),
+                        },
+                    }
+                }
+                if let Some(rate) = numbers.first() {
+                    strum.rate = *rate;
+                }
+                if let Some(curve) = numbers.get(1) {
+                    strum.curve = *curve;
+                }
+                println!(
+                    "Strumming {:?} at {} voices per second, curve {}.",
+                    strum.direction, strum.rate, strum.curve
+                );
+                options.strum = Some(strum);
+            }
+            cmd if cmd.starts_with("wait ") => {
+                let Ok(millis) = cmd.trim_start_matches("wait ").trim().parse() else {
+                    println!("Failed to parse time.");
+                    continue;
+                };
+                if let Err(e) = backend.advance(Duration::from_millis(millis)) {
+                    println!("{}", e);
+                }
+            }
+            "effect" => {
+                if chain.is_empty() {
+                    println!("No effects.");
+                }
+                for (idx, effect) in chain.iter().enumerate() {
+                    println!("{}: {:?}", idx + 1, effect);
+                }
+            }
+            "effect clear" => {
+                chain.clear();
+                restart_engine(backend.as_mut(), &mut engine, &chain);
+                println!("Removed all effects.");
+            }
+            cmd if cmd.starts_with("effect ") => {
+                let mut args = cmd.split_whitespace().skip(1);
+                let kind = args.next().unwrap_or_default();
+                let values: Vec<f32> = match args.map(str::parse).collect::<Result<_, _>>() {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to parse effect: {}", e);
+                        continue;
+                    }
+                };
+                if values.iter().any(|el| !el.is_finite()) {
+                    println!("Effect settings must be finite.");
+                    continue;
+                }
+                let value = |idx: usize, default: f32| values.get(idx).copied().unwrap_or(default);
+                let effect = match kind {
+                    "lowpass" => Effect::LowPass {
+                        cutoff: value(0, 2000.),
+                        resonance: value(1, 0.707),
+                    },
+                    "delay" => {
+                        let Some(time) =
+                            Duration::try_from_secs_f32(value(0, 300.).max(1.) / 1000.)
+                                .ok()
+                                .filter(|el| *el <= effects::MAX_DELAY)
+                        else {
+                            println!("Delay time is too long.");
+                            continue;
+                        };
+                        Effect::Delay {
+                            time,
+                            feedback: value(1, 0.4),
+                            mix: value(2, 0.3),
+                        }
+                    }
+                    "reverb" => Effect::Reverb {
+                        room_size: value(0, 0.7),
+                        damping: value(1, 0.5),
+                        mix: value(2, 0.3),
+                    },
+                    other => {
+                        println!("Unknown effect: {}", other);
+                        continue;
+                    }
+                };
+                println!("Added {:?}", effect);
+                chain.push(effect);
+                restart_engine(backend.as_mut(), &mut engine, &chain);
+            }
+            "glide off" => {
+                portamento = None;
+                println!("New chords start on their own.");
+            }
+            cmd if cmd.starts_with("glide ") => {
+                let mut args = cmd.split_whitespace().skip(1);
+                let Some(Ok(millis)) = args.next().map(str::parse) else {
+                    println!("Failed to parse glide time.");
+                    continue;
+                };
+                let path = match (args.next(), args.next().map(str::parse::<f32>)) {
+                    (None, _) => GlidePath::Direct,
+                    (Some("lattice"), None) => GlidePath::Lattice { portamento: 0.5 },
+                    (Some("lattice"), Some(Ok(o))) => GlidePath::Lattice { portamento: o },
+                    _ => {
+                        println!("Failed to parse glide path.");
+                        continue;
+                    }
+                };
+                portamento = Some(Portamento {
+                    duration: Duration::from_millis(millis),
+                    path,
+                });
+                println!("New chords glide from the playing ones: {:?}", path);
+            }
+            "vibrato off" => {
+                modulation.vibrato = None;
+                println!("Stopped vibrato.");
+            }
+            cmd if cmd.starts_with("vibrato ") => {
+                let Some(lfo) = parse_lfo(cmd) else {
+                    println!("Failed to parse vibrato.");
+                    continue;
+                };
+                modulation.vibrato = Some(lfo);
+                println!("Vibrato of {} cents at {} Hz.", lfo.depth, lfo.rate);
+            }
+            "tremolo off" => {
+                modulation.tremolo = None;
+                println!("Stopped tremolo.");
+            }
+            cmd if cmd.starts_with("tremolo ") => match parse_lfo(cmd) {
+                Some(lfo) if (0. ..=1.).contains(&lfo.depth) => {
+                    modulation.tremolo = Some(lfo);
+                    println!("Tremolo of depth {} at {} Hz.", lfo.depth, lfo.rate);
+                }
+                Some(_) => println!("Depth must be between 0 and 1."),
+                None => println!("Failed to parse tremolo."),
+            },
+            cmd if cmd.starts_with("spread") => {
+                let mut args = cmd.split_whitespace().skip(1);
+                let kind = args.next().unwrap_or("center");
+                let width = match args.next().map(str::parse::<f32>) {
+                    None => 1.,
+                    Some(Ok(o)) if (0. ..=1.).contains(&o) => o,
+                    Some(_) => {
+                        println!("Width must be between 0 and 1.");
+                        continue;
+                    }
+                };
+                options.spread = match kind {
+                    "center" => Spread::Center,
+                    "pitch" => Spread::Pitch(width),
+                    "lattice" => Spread::Lattice(width),
+                    other => {
+                        println!("Unknown spread: {}", other);
+                        continue;
+                    }
+                };
+                println!("Spreading voices: {:?}", options.spread);
+            }
             "help" => {
                 println!("Displaying help.");
                 println!(
@@ -76,13 +322,326 @@ Commands:
     help: Display this help message.
     stack: Toggle stacking, which will store all playing notes for gathering, and will not stop the sound.
     gather: Gather currently playing sounds to output a chord. Only for use in "stack" mode
-    strum: Strum all chords instead of playing them all at once.
-    stop: Stop all sounds.
+    strum: Toggle strumming all chords instead of playing them all at once.
+    strum <up|down|alternating> [rate] [curve]: Strum in a direction, starting [rate] voices per second. A [curve] above 1 spreads out the last voices, below 1 the first ones.
+    spread <center|pitch|lattice> [width]: Place the voices of new chords across the stereo field by pitch or by lattice position, [width] from 0 to 1.
+    effect lowpass [cutoff] [resonance]: Add a low-pass filter with a cutoff in Hz to the effects of new chords.
+    effect delay [ms] [feedback] [mix]: Add an echo.
+    effect reverb [room size] [damping] [mix]: Add a reverb. All three range from 0 to 1.
+    effect: List the effects. "effect clear" removes them all.
+    glide <ms> [lattice [portamento]]: Glide the notes of the playing chord to the next chord instead of starting it anew. With "lattice", step through the lattice points on the way, gliding over [portamento] (0 to 1) of each step.
+    glide off: Start every chord anew.
+    vibrato <rate> <cents> [fade]: Wobble the pitch of new notes <cents> up and down <rate> times per second, fading in over [fade] milliseconds. "vibrato off" stops it.
+    tremolo <rate> <depth> [fade]: Wobble the volume of new notes by <depth> (0 to 1). "tremolo off" stops it.
+    cache: Show how many wavetables are shared between voices and how often they are reused.
+    level: Show the peak level since the last "level" and the current RMS level.
+    wait <ms>: Render the next <ms> milliseconds when writing to a file or running without an audio device.
+    stop: Stop all sounds. They fade out over the release of their envelope.
+    timbre <ratio>:<amplitude> ...: Play new chords with additive partials, e.g. "timbre 1:1 2:0.5 2.76:0.3".
+    fm [ratio] [index] [feedback]: Play new chords with FM, a modulator at [ratio] times the pitch bending a sine by up to [index] radians.
+    pluck [decay] [brightness]: Play new chords as plucked strings that fade over [decay] seconds, [brightness] from 0 to 1. Try it with "strum".
+    timbre matched: Play each chord with a spectrum whose partials coincide with its own tones.
+    sample <file> <root> [loop start] [loop end]: Play new chords by resampling a WAV recording of the pitch <root> Hz, optionally looping between two frames.
+    wave <sine|square|saw|triangle> <naive|additive|polyblep>: Set the waveform of new chords and how it is band-limited. Either argument may be left out.
+    envelope <attack> <decay> <sustain> <release>: Set the envelope of new chords, times in milliseconds and sustain between 0 and 1. Without arguments, show the current one.
+    score <file>: Play a score written in the score language with the current instrument, envelope, vibrato, tremolo and effects, and add it to the project as a track. With "--wav", it is rendered as it plays.
+    play: Play all tracks of the project that are not muted.
+    save [file]: Save the project along with the current instrument, envelope, glide, vibrato, tremolo and effects. Without [file], save to the file it was last opened from or saved to.
+    open <file>: Open a project and take over its instrument and other settings.
+    Unsaved changes are kept in a recovery file every 30 seconds, and offered again after a crash.
+    transcribe <file>: Detect the sustained pitches of a WAV recording and print them as harmonyms.
     exit: Exit this program.
                     "#
                 )
             }
-            "exit" => return,
+            "exit" if session.is_modified() && !exiting => {
+                println!(
+                    "There are unsaved changes. Save them with \"save\", or enter \"exit\" again to discard them."
+                );
+                exiting = true;
+            }
+            "exit" => break true,
+            cmd if cmd.starts_with("wave ") => {
+                let (mut waveform, mut bandlimit) = match instrument {
+                    Instrument::Wave {
+                        waveform,
+                        bandlimit,
+                    } => (waveform, bandlimit),
+                    _ => (DEFAULT_WAVE, Bandlimit::default()),
+                };
+                for arg in cmd.split_whitespace().skip(1) {
+                    match arg {
+                        "sine" => waveform = Waveform::Sine,
+                        "square" => waveform = Waveform::Square,
+                        "saw" => waveform = Waveform::Saw,
+                        "triangle" => waveform = Waveform::Triangle,
+                        "naive" => bandlimit = Bandlimit::Naive,
+                        "additive" => bandlimit = Bandlimit::Additive,
+                        "polyblep" => bandlimit = Bandlimit::PolyBlep,
+                        other => println!("Unknown waveform or band-limiting: {}", other),
+                    }
+                }
+                instrument = Instrument::Wave {
+                    waveform,
+                    bandlimit,
+                };
+                match_timbre = false;
+                println!(
+                    "Playing {:?} waves, {:?} band-limiting.",
+                    waveform, bandlimit
+                );
+            }
+            cmd if cmd == "fm" || cmd.starts_with("fm ") => {
+                let mut fm = Fm::default();
+                let mut args = cmd.split_whitespace().skip(1).map(str::parse::<f32>);
+                let fields = [&mut fm.ratio, &mut fm.index, &mut fm.feedback];
+                if fields
+                    .into_iter()
+                    .zip(&mut args)
+                    .any(|(field, arg)| arg.map(|el| *field = el).is_err())
+                {
+                    println!("Failed to parse FM settings.");
+                    continue;
+                }
+                instrument = fm.into();
+                match_timbre = false;
+                println!(
+                    "Playing FM with ratio {}, index {}, feedback {}.",
+                    fm.ratio, fm.index, fm.feedback
+                );
+            }
+            cmd if cmd == "pluck" || cmd.starts_with("pluck ") => {
+                let mut pluck = Pluck::default();
+                let mut args = cmd.split_whitespace().skip(1).map(str::parse::<f32>);
+                let fields = [&mut pluck.decay, &mut pluck.brightness];
+                if fields
+                    .into_iter()
+                    .zip(&mut args)
+                    .any(|(field, arg)| arg.map(|el| *field = el).is_err())
+                {
+                    println!("Failed to parse pluck settings.");
+                    continue;
+                }
+                instrument = pluck.into();
+                match_timbre = false;
+                println!(
+                    "Plucking strings with a {} s decay, brightness {}.",
+                    pluck.decay, pluck.brightness
+                );
+            }
+            "timbre matched" => {
+                match_timbre = true;
+                println!("Each chord plays with a timbre matched to its own tones.");
+            }
+            cmd if cmd.starts_with("timbre ") => {
+                let partials: Result<Vec<Partial>, String> = cmd
+                    .split_whitespace()
+                    .skip(1)
+                    .map(|el| {
+                        let (ratio, amplitude) = el.split_once(':').unwrap_or((el, "1"));
+                        match (ratio.parse::<f32>(), amplitude.parse::<f32>()) {
+                            (Ok(ratio), Ok(amplitude))
+                                if ratio.is_finite() && amplitude.is_finite() =>
+                            {
+                                Ok(Partial { ratio, amplitude })
+                            }
+                            _ => Err(el.to_owned()),
+                        }
+                    })
+                    .collect();
+                match partials {
+                    Ok(o) if o.iter().all(|el| el.amplitude == 0.) => {
+                        println!("A timbre needs at least one partial that is not silent.");
+                    }
+                    Ok(o) => {
+                        instrument = Timbre::from(o).into();
+                        match_timbre = false;
+                        println!("Timbre set.");
+                    }
+                    Err(e) => println!("Failed to parse partial: {}", e),
+                }
+            }
+            cmd if cmd.starts_with("sample ") => {
+                let args: Vec<&str> = cmd.split_whitespace().skip(1).collect();
+                let (path, root, looping) = match args[..] {
+                    [path, root] => (path, root.parse(), None),
+                    [path, root, start, end] => (path, root.parse(), Some((start, end))),
+                    _ => {
+                        println!("Usage: sample <file> <root Hz> [loop start] [loop end]");
+                        continue;
+                    }
+                };
+                let Ok(root) = root else {
+                    println!("Failed to parse root frequency.");
+                    continue;
+                };
+                let mut sample = match Sample::load(path, root) {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to load sample: {}", e);
+                        continue;
+                    }
+                };
+                if let Some((start, end)) = looping {
+                    let result = match (start.parse(), end.parse()) {
+                        (Ok(start), Ok(end)) => sample.set_loop(start, end),
+                        _ => Err(SampleError::InvalidLoop),
+                    };
+                    if let Err(e) = result {
+                        println!("Failed to set loop: {}", e);
+                        continue;
+                    }
+                }
+                instrument = sample.into();
+                match_timbre = false;
+                println!("Playing the sample.");
+            }
+            cmd if cmd.starts_with("envelope") => {
+                let values: Vec<f32> = match cmd
+                    .split_whitespace()
+                    .skip(1)
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to parse envelope: {}", e);
+                        continue;
+                    }
+                };
+                let [attack, decay, sustain, release] = values[..] else {
+                    println!(
+                        "Envelope is attack {:?}, decay {:?}, sustain {}, release {:?}",
+                        envelope.attack, envelope.decay, envelope.sustain, envelope.release
+                    );
+                    continue;
+                };
+                let [Ok(attack), Ok(decay), Ok(release)] =
+                    [attack, decay, release].map(|el| Duration::try_from_secs_f32(el / 1000.))
+                else {
+                    println!("Envelope times must be finite and not negative.");
+                    continue;
+                };
+                if !(0. ..=1.).contains(&sustain) {
+                    println!("Sustain must be between 0 and 1.");
+                    continue;
+                }
+                envelope = Adsr {
+                    attack,
+                    decay,
+                    sustain,
+                    release,
+                };
+                println!("Envelope set.");
+            }
+            cmd if cmd.starts_with("score ") => {
+                let path = cmd.trim_start_matches("score ").trim();
+                let mut score = match dsl::load(path) {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to load score: {}", e);
+                        continue;
+                    }
+                };
+                for note in score.notes_mut() {
+                    note.envelope = envelope;
+                    note.modulation = modulation;
+                }
+                score.set_instrument(instrument.clone());
+                score.set_effects(chain.clone());
+                score.set_sample_rate(backend.sample_rate());
+                println!(
+                    "Playing {} notes over {:.1} seconds.",
+                    score.notes().len(),
+                    score.tempo().seconds(score.length())
+                );
+                let name = std::path::Path::new(path)
+                    .file_stem()
+                    .map_or_else(|| path.to_owned(), |el| el.to_string_lossy().into_owned());
+                session.project_mut().tracks.push(Track {
+                    name,
+                    muted: false,
+                    score: score.clone(),
+                });
+                if let Err(e) = backend.play(BlockRenderer::from(score).with_effects()) {
+                    println!("Failed to play score: {}", e);
+                }
+            }
+            "play" => {
+                let tracks = &session.project().tracks;
+                if tracks.is_empty() {
+                    println!("No tracks. Add one with \"score <file>\".");
+                }
+                for track in tracks.iter().filter(|el| !el.muted) {
+                    println!("Playing {}.", track.name);
+                    let mut score = track.score.clone();
+                    score.set_sample_rate(backend.sample_rate());
+                    if let Err(e) = backend.play(BlockRenderer::from(score).with_effects()) {
+                        println!("Failed to play {}: {}", track.name, e);
+                    }
+                }
+            }
+            cmd if cmd == "save" || cmd.starts_with("save ") => {
+                let result = match cmd.strip_prefix("save").unwrap_or_default().trim() {
+                    "" => session.save(),
+                    path => session.save_as(path),
+                };
+                match result {
+                    Ok(()) => println!("Project saved."),
+                    Err(e) => println!("Failed to save project: {}", e),
+                }
+            }
+            cmd if cmd.starts_with("open ") => {
+                let path = Path::new(cmd.trim_start_matches("open ").trim());
+                let opened = match offer_recovery(&mut lines, Some(path)) {
+                    Some(o) => Ok(o),
+                    None => SessionData::open(path),
+                };
+                *session = match opened {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to open project: {}", e);
+                        continue;
+                    }
+                };
+                let editor = &session.project().editor;
+                instrument = editor.instrument.clone();
+                envelope = editor.envelope;
+                modulation = editor.modulation;
+                portamento = editor.portamento;
+                if chain != editor.effects {
+                    chain = editor.effects.clone();
+                    restart_engine(backend.as_mut(), &mut engine, &chain);
+                }
+                for track in &session.project().tracks {
+                    println!(
+                        "{}: {} notes{}",
+                        track.name,
+                        track.score.notes().len(),
+                        if track.muted { ", muted" } else { "" }
+                    );
+                }
+            }
+            cmd if cmd.starts_with("transcribe ") => {
+                let path = cmd.trim_start_matches("transcribe ").trim();
+                let score = match transcribe::transcribe_file(path, &Default::default()) {
+                    Ok(o) => o,
+                    Err(e) => {
+                        println!("Failed to transcribe: {}", e);
+                        continue;
+                    }
+                };
+                for note in score.notes() {
+                    let start = score.tempo().seconds(note.duration.start);
+                    println!(
+                        "{:>10} {:>8.3}s {:>8.3}s {}",
+                        score.tempo().position(note.duration.start).to_string(),
+                        start,
+                        score.tempo().seconds(note.duration.end()) - start,
+                        note.harmonym
+                    );
+                }
+            }
             chord => {
                 let mut chord: Chord = match Chord::parse_chord(chord) {
                     Ok(o) => o.1,
@@ -102,27 +661,181 @@ Commands:
                 );
                 if stack {
                     harmonyms.append(&mut chord.tones.clone());
-                    let mut playable_chord: PlayableChord = chord.into();
-                    playable_chord.prerender();
-                    sink.append(playable_chord.amplify(0.2));
+                }
+                let instrument = if match_timbre {
+                    Timbre::matched(&chord.tones, 2).into()
                 } else {
-                    let mut playable_chord: PlayableChord = chord.into();
-                    playable_chord.prerender();
-                    sink.append(
-                        playable_chord
-                            .take_duration(Duration::from_secs(3))
-                            .amplify(0.2),
-                    );
+                    instrument.clone()
+                };
+                let hold = (!stack).then_some(Duration::from_secs(3));
+                let result = engine
+                    .set_instrument(instrument)
+                    .and_then(|_| engine.set_envelope(envelope))
+                    .and_then(|_| engine.set_portamento(portamento))
+                    .and_then(|_| engine.set_modulation(modulation))
+                    .and_then(|_| engine.chord(&chord.tones, &options, hold));
+                if let Err(e) = result {
+                    println!("Failed to play chord: {}", e);
                 }
-                sinks.push(sink);
+                options.advance();
             }
         }
+    };
+    let mut session = lock(&shared);
+    if exited {
+        session.discard_recovery().ok();
+    } else if session.is_modified() {
+        // Input ended without "exit", so keep any unsaved changes to be offered next time.
+        match session.autosave() {
+            Ok(_) => println!(
+                "Unsaved changes are kept in {}.",
+                session.recovery_path().display()
+            ),
+            Err(e) => println!("Failed to keep unsaved changes: {}", e),
+        }
+    }
+    // Let the last notes ring out, which offline backends render here.
+    engine.finish().ok();
+    if let Err(e) = backend.finish() {
+        println!("{}", e);
+    }
+}
+
+/// Picks the audio backend from the command line. `--wav <file>` renders into a file and `--null` discards the
+/// audio, otherwise the audio device is used, falling back to `--null` when there is none. `--rate <hz>` sets the
+/// sample rate and `--quality <draft|standard|high>` the quality of any rate conversion.
+#[cfg(feature = "cli")]
+fn select_backend() -> Box<dyn AudioBackend> {
+    let mut args = std::env::args().skip(1);
+    let mut wav = None;
+    let mut null = false;
+    let mut rate = None;
+    let mut quality = Quality::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--null" => null = true,
+            "--wav" => wav = args.next(),
+            "--rate" => match args.next().map(|el| el.parse()) {
+                Some(Ok(o)) if o > 0 => rate = Some(o),
+                _ => println!("Ignoring invalid sample rate."),
+            },
+            "--quality" => match args.next().as_deref() {
+                Some("draft") => quality = Quality::Draft,
+                Some("standard") => quality = Quality::Standard,
+                Some("high") => quality = Quality::High,
+                _ => println!("Ignoring invalid quality."),
+            },
+            other => println!("Ignoring invalid argument: {}", other),
+        }
     }
+    let offline_rate = rate.unwrap_or(SAMPLE_RATE);
+    let mut backend: Box<dyn AudioBackend> = match wav {
+        _ if null => Box::new(NullBackend::new(offline_rate)),
+        Some(path) => match WavBackend::create(&path, offline_rate) {
+            Ok(o) => {
+                println!("Rendering to {}. Use \"wait <ms>\" to let time pass.", path);
+                Box::new(o)
+            }
+            Err(e) => {
+                println!("{}, discarding audio instead.", e);
+                Box::new(NullBackend::new(offline_rate))
+            }
+        },
+        None => match RodioBackend::with_sample_rate(rate) {
+            Ok(o) => Box::new(o),
+            Err(e) => {
+                println!("{}, discarding audio instead.", e);
+                Box::new(NullBackend::new(offline_rate))
+            }
+        },
+    };
+    backend.set_quality(quality);
+    backend
+}
+
+/// Starts an engine on `backend`, running through the effects of `chain`.
+#[cfg(feature = "cli")]
+fn start_engine(
+    backend: &mut dyn AudioBackend,
+    chain: &[Effect],
+) -> Result<EngineHandle, BackendError> {
+    let (engine, handle) = Engine::new(backend.sample_rate(), DEFAULT_POLYPHONY);
+    backend.play(effects::chain(engine, chain))?;
+    Ok(handle)
+}
+
+/// Starts a new engine and lets the notes of the old one ring out, e.g. after the effects changed.
+#[cfg(feature = "cli")]
+fn restart_engine(backend: &mut dyn AudioBackend, engine: &mut EngineHandle, chain: &[Effect]) {
+    match start_engine(backend, chain) {
+        Ok(o) => {
+            engine.finish().ok();
+            *engine = o;
+        }
+        Err(e) => println!("Failed to restart the engine: {}", e),
+    }
+}
+
+/// The session shared with the autosave thread.
+#[cfg(feature = "cli")]
+fn lock(shared: &Mutex<SessionData>) -> std::sync::MutexGuard<'_, SessionData> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Asks whether to take over the unsaved changes a session left behind when it did not end properly, for the project
+/// at `project` or for one that was never saved. Declined changes are discarded.
+#[cfg(feature = "cli")]
+fn offer_recovery(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+    project: Option<&Path>,
+) -> Option<SessionData> {
+    let session = match SessionData::recover(project) {
+        Ok(o) => o?,
+        Err(e) => {
+            println!("Failed to read unsaved changes: {}", e);
+            return None;
+        }
+    };
+    println!(
+        "Found unsaved changes from a session that did not end properly in {}. Recover them? [y/n]",
+        session.recovery_path().display()
+    );
+    match lines.next() {
+        Some(Ok(answer)) if answer.trim().eq_ignore_ascii_case("y") => {
+            println!(
+                "Recovered {} tracks. Save them to keep them.",
+                session.project().tracks.len()
+            );
+            Some(session)
+        }
+        _ => {
+            session.discard_recovery().ok();
+            println!("Discarded the unsaved changes.");
+            None
+        }
+    }
+}
+
+/// Parses the `<rate> <depth> [fade ms]` arguments of the `vibrato` and `tremolo` commands.
+#[cfg(feature = "cli")]
+fn parse_lfo(cmd: &str) -> Option<Lfo> {
+    let mut args = cmd.split_whitespace().skip(1).map(str::parse::<f32>);
+    let mut next = || args.next().map(|el| el.ok().filter(|el| el.is_finite()));
+    let rate = next()??;
+    let depth = next()??;
+    let delay = match next() {
+        Some(millis) => Duration::try_from_secs_f32(millis?.max(0.) / 1000.).ok()?,
+        None => Duration::ZERO,
+    };
+    Some(Lfo {
+        delay,
+        ..Lfo::new(rate, depth)
+    })
 }
 
-#[cfg(not(target_feature = "cli"))]
+#[cfg(not(feature = "cli"))]
 fn main() {
-    use crate::gui::init;
-    let (_stream, handle) = OutputStream::try_default().unwrap();
+    use gui::init;
+    let _backend = RodioBackend::new().inspect_err(|e| println!("{}", e));
     init();
 }
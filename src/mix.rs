@@ -0,0 +1,144 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+/// Gain that brings `voices` summed voices back to the loudness of a single one.
+///
+/// Voices tuned to different harmonyms are mostly uncorrelated, so their power adds up rather than their amplitude.
+pub fn voice_gain(voices: usize) -> f32 {
+    1. / (voices.max(1) as f32).sqrt()
+}
+
+/// Places a mono sample in the stereo field with an equal-power pan law. -1 is hard left, 1 hard right.
+pub fn pan(sample: f32, position: f32) -> [f32; 2] {
+    let angle = (position.clamp(-1., 1.) + 1.) * std::f32::consts::FRAC_PI_4;
+    [sample * angle.cos(), sample * angle.sin()]
+}
+
+fn decibels(amplitude: f32) -> f32 {
+    20. * amplitude.max(1e-6).log10()
+}
+
+/// Peak and RMS level of a source, readable from another thread while it plays.
+#[derive(Debug, Clone, Default)]
+pub struct Meter(Arc<(AtomicU32, AtomicU32)>);
+
+impl Meter {
+    /// Highest absolute sample since the last [`Meter::reset`].
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.0.0.load(Ordering::Relaxed))
+    }
+
+    /// RMS level over roughly the last 300 ms.
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.0.1.load(Ordering::Relaxed))
+    }
+
+    pub fn peak_db(&self) -> f32 {
+        decibels(self.peak())
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        decibels(self.rms())
+    }
+
+    pub fn reset(&self) {
+        self.0.0.store(0, Ordering::Relaxed);
+    }
+
+    fn record(&self, peak: f32, rms: f32) {
+        self.0.0.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        self.0.1.store(rms.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Level the output never exceeds.
+const CEILING: f32 = 0.8;
+/// Level above which the limiter starts to bend peaks towards the ceiling.
+const KNEE: f32 = 0.5;
+const LIMITER_RELEASE: Duration = Duration::from_millis(150);
+const GAIN_SMOOTHING: Duration = Duration::from_millis(20);
+const RMS_WINDOW: Duration = Duration::from_millis(300);
+
+/// Final stage of every render path: voice-count normalisation, a soft-knee peak limiter with a smooth release and a
+/// meter.
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    /// Normalisation gain, eased towards [`voice_gain`] so voices starting or ending do not click.
+    gain: f32,
+    gain_coefficient: f32,
+    /// Peak envelope followed by the limiter.
+    envelope: f32,
+    release_coefficient: f32,
+    mean_square: f32,
+    rms_coefficient: f32,
+    meter: Meter,
+}
+
+/// Per-frame coefficient of a one-pole smoother that settles within `time`.
+fn coefficient(time: Duration, sample_rate: u32) -> f32 {
+    (-1. / (time.as_secs_f32() * sample_rate as f32)).exp()
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            gain: 1.,
+            gain_coefficient: coefficient(GAIN_SMOOTHING, sample_rate),
+            envelope: 0.,
+            release_coefficient: coefficient(LIMITER_RELEASE, sample_rate),
+            mean_square: 0.,
+            rms_coefficient: coefficient(RMS_WINDOW, sample_rate),
+            meter: Meter::default(),
+        }
+    }
+
+    /// Recomputes the time constants for `sample_rate`, keeping the current levels.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.gain_coefficient = coefficient(GAIN_SMOOTHING, sample_rate);
+        self.release_coefficient = coefficient(LIMITER_RELEASE, sample_rate);
+        self.rms_coefficient = coefficient(RMS_WINDOW, sample_rate);
+    }
+
+    /// Sets the normalisation gain at once, e.g. before the first frame of a chord.
+    pub fn set_voices(&mut self, voices: usize) {
+        self.gain = voice_gain(voices);
+    }
+
+    /// Scales the stereo `sum` of `voices` voices, limits it and records its level.
+    pub fn mix(&mut self, sum: [f32; 2], voices: usize) -> [f32; 2] {
+        self.gain = voice_gain(voices) + (self.gain - voice_gain(voices)) * self.gain_coefficient;
+        let frame = sum.map(|el| el * self.gain);
+        let peak = frame[0].abs().max(frame[1].abs());
+
+        // The envelope jumps to new peaks and falls back slowly, so the gain reduction eases off instead of pumping.
+        // Both channels share it, which keeps the stereo image in place.
+        self.envelope = peak.max(self.envelope * self.release_coefficient);
+        let out = if self.envelope > KNEE {
+            let gain = soft_knee(self.envelope) / self.envelope;
+            frame.map(|el| el * gain)
+        } else {
+            frame
+        };
+
+        let power = (out[0] * out[0] + out[1] * out[1]) / 2.;
+        self.mean_square = power + (self.mean_square - power) * self.rms_coefficient;
+        self.meter
+            .record(out[0].abs().max(out[1].abs()), self.mean_square.sqrt());
+        out
+    }
+
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+}
+
+/// Passes levels up to [`KNEE`] unchanged and bends louder ones smoothly towards [`CEILING`] without reaching it.
+fn soft_knee(level: f32) -> f32 {
+    let range = CEILING - KNEE;
+    KNEE + range * ((level - KNEE) / range).tanh()
+}
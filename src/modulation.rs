@@ -0,0 +1,161 @@
+use std::{
+    f32::consts::PI,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::note::Harmonym;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+}
+
+/// A low-frequency oscillator driving one parameter of a voice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    /// Cycles per second.
+    pub rate: f32,
+    /// Swing in cents for vibrato and in gain (0 to 1) for tremolo.
+    pub depth: f32,
+    /// Time the modulation takes to fade in after the note starts.
+    pub delay: Duration,
+}
+
+impl Lfo {
+    pub fn new(rate: f32, depth: f32) -> Self {
+        Self {
+            shape: LfoShape::default(),
+            rate,
+            depth,
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Pitch and amplitude modulation of a voice.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Modulation {
+    pub vibrato: Option<Lfo>,
+    pub tremolo: Option<Lfo>,
+}
+
+impl Modulation {
+    pub fn is_none(&self) -> bool {
+        self.vibrato.is_none() && self.tremolo.is_none()
+    }
+}
+
+/// Seed for the LFO phases of the voice playing `harmonym` as the `idx`th voice of a chord or score.
+///
+/// Deterministic on purpose, so a score renders the same way every time; its notes differ in `idx`, so repeated chords
+/// still get their own phases. Live playing mixes in [`fresh_seed`] or the frame a note starts on.
+pub fn voice_seed(harmonym: Harmonym, idx: usize) -> u64 {
+    harmonym
+        .exponents()
+        .iter()
+        .fold(idx as u64, |acc, el| (acc << 8) ^ *el as u8 as u64)
+}
+
+/// A different seed on every call, e.g. for each time a chord is played from the REPL.
+pub fn fresh_seed() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    split_mix(NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// SplitMix64, a small hash that spreads neighbouring seeds far apart.
+pub(crate) fn split_mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Running state of an [`Lfo`].
+#[derive(Debug, Clone)]
+struct LfoState {
+    lfo: Lfo,
+    /// Position in the cycle, from 0 to 1.
+    phase: f32,
+    increment: f32,
+    fade_frames: u32,
+    frame: u32,
+}
+
+impl LfoState {
+    fn new(lfo: Lfo, seed: u64, sample_rate: u32) -> Self {
+        Self {
+            lfo,
+            phase: (split_mix(seed) >> 40) as f32 / (1u64 << 24) as f32,
+            increment: lfo.rate / sample_rate as f32,
+            fade_frames: (lfo.delay.as_secs_f64() * sample_rate as f64) as u32,
+            frame: 0,
+        }
+    }
+}
+
+impl Iterator for LfoState {
+    type Item = f32;
+
+    /// Value from -1 to 1, scaled down while fading in.
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = match self.lfo.shape {
+            LfoShape::Sine => (2. * PI * self.phase).sin(),
+            LfoShape::Triangle => 1. - 4. * ((self.phase + 0.25).fract() - 0.5).abs(),
+        };
+        self.phase = (self.phase + self.increment).fract();
+        let fade = if self.frame < self.fade_frames {
+            self.frame += 1;
+            self.frame as f32 / self.fade_frames as f32
+        } else {
+            1.
+        };
+        Some(value * fade)
+    }
+}
+
+/// [`Modulation`] running on one voice. Yields the frequency ratio and gain to apply on each frame.
+#[derive(Debug, Clone)]
+pub struct Modulator {
+    vibrato: Option<LfoState>,
+    tremolo: Option<LfoState>,
+}
+
+impl Modulator {
+    /// Starts `modulation` with LFO phases picked from `seed`, see [`voice_seed`].
+    pub fn new(modulation: &Modulation, seed: u64, sample_rate: u32) -> Self {
+        Self {
+            vibrato: modulation
+                .vibrato
+                .map(|el| LfoState::new(el, split_mix(seed), sample_rate)),
+            tremolo: modulation
+                .tremolo
+                .map(|el| LfoState::new(el, split_mix(seed ^ 1), sample_rate)),
+        }
+    }
+
+    /// Whether the voice needs its frequency updated on every frame.
+    pub fn has_vibrato(&self) -> bool {
+        self.vibrato.is_some()
+    }
+}
+
+impl Iterator for Modulator {
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ratio = match &mut self.vibrato {
+            Some(el) => 2f32.powf(el.next().unwrap() * el.lfo.depth / 1200.),
+            None => 1.,
+        };
+        // Tremolo only ever takes gain away, swinging between full level and `1 - depth`.
+        let gain = match &mut self.tremolo {
+            Some(el) => 1. - el.lfo.depth.clamp(0., 1.) * (1. + el.next().unwrap()) / 2.,
+            None => 1.,
+        };
+        Some((ratio, gain))
+    }
+}
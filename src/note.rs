@@ -2,12 +2,16 @@ use std::fmt;
 use std::fmt::Display;
 use std::ops::MulAssign;
 use std::str::FromStr;
-use std::{error::Error, ops::Mul};
+use std::{
+    error::Error,
+    ops::{Div, Mul},
+};
 
 use nom::Parser;
 use nom::character::complete::char;
 use nom::multi::many0;
 use nom::{IResult, error::ErrorKind};
+use num::traits::Inv;
 use num::{Integer, One, pow};
 use phf::phf_map;
 
@@ -39,7 +43,7 @@ impl From<Harmonym> for FullNote {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Harmonym {
     notes: [NotePart; 5],
 }
@@ -92,43 +96,28 @@ impl Display for Harmonym {
 impl Default for Harmonym {
     fn default() -> Self {
         Self {
-            notes: [
-                NotePart {
-                    dimension: 1,
-                    degree: 0,
-                },
-                NotePart {
-                    dimension: 2,
-                    degree: 0,
-                },
-                NotePart {
-                    dimension: 3,
-                    degree: 0,
-                },
-                NotePart {
-                    dimension: 4,
-                    degree: 0,
-                },
-                NotePart {
-                    dimension: 5,
-                    degree: 0,
-                },
-            ],
+            notes: std::array::from_fn(|idx| NotePart {
+                dimension: idx as u8 + 1,
+                degree: 0,
+            }),
         }
     }
 }
 
+/// Highest degree that has a name along any dimension, in either direction.
+pub const MAX_DEGREE: i8 = 3;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Ratio {
-    dividend: u32,
-    divisor: u32,
+    dividend: u64,
+    divisor: u64,
 }
 
 impl From<(u32, u32)> for Ratio {
     fn from(value: (u32, u32)) -> Self {
         Self {
-            dividend: value.0,
-            divisor: value.1,
+            dividend: value.0.into(),
+            divisor: value.1.into(),
         }
     }
 }
@@ -139,6 +128,8 @@ impl From<Harmonym> for Ratio {
     }
 }
 
+const PRIMES: [u64; 5] = [2, 3, 5, 7, 11];
+
 fn dim2frac(dim: u8) -> Ratio {
     match dim {
         0 => (1, 1),
@@ -170,10 +161,12 @@ impl From<Ratio> for f32 {
 impl Mul for Ratio {
     type Output = Ratio;
     fn mul(self, rhs: Self) -> Self::Output {
-        let gcd = (self.dividend * rhs.dividend).gcd(&(self.divisor * rhs.divisor));
+        // Reduce crosswise first so the products stay as small as the result.
+        let left = self.dividend.gcd(&rhs.divisor);
+        let right = rhs.dividend.gcd(&self.divisor);
         Self {
-            dividend: self.dividend * rhs.dividend / gcd,
-            divisor: self.divisor * rhs.divisor / gcd,
+            dividend: (self.dividend / left) * (rhs.dividend / right),
+            divisor: (self.divisor / right) * (rhs.divisor / left),
         }
     }
 }
@@ -212,6 +205,103 @@ impl Display for Ratio {
 }
 
 impl Harmonym {
+    /// Builds a harmonym from its exponent vector (monzo), ordered from dimension 1 (octave) to 5.
+    /// Returns `None` if a degree lies beyond [`MAX_DEGREE`] in either direction.
+    pub fn from_exponents(exponents: [i8; 5]) -> Option<Self> {
+        exponents
+            .iter()
+            .all(|el| (-MAX_DEGREE..=MAX_DEGREE).contains(el))
+            .then(|| Self {
+                notes: std::array::from_fn(|idx| NotePart {
+                    dimension: idx as u8 + 1,
+                    degree: exponents[idx],
+                }),
+            })
+    }
+
+    /// The exponent vector (monzo) of this harmonym, ordered from dimension 1 (octave) to 5.
+    pub fn exponents(&self) -> [i8; 5] {
+        self.notes.map(|el| el.degree)
+    }
+
+    /// Degree along `dimension` (1..=5), or `None` if the dimension does not exist.
+    pub fn degree(&self, dimension: u8) -> Option<i8> {
+        self.notes
+            .iter()
+            .find(|el| el.dimension == dimension)
+            .map(|el| el.degree)
+    }
+
+    /// Sets the degree along `dimension` (1..=5). Returns `None` if the dimension does not exist
+    /// or the degree lies beyond [`MAX_DEGREE`].
+    pub fn set_degree(&mut self, dimension: u8, degree: i8) -> Option<()> {
+        let part = self.notes.iter_mut().find(|el| el.dimension == dimension)?;
+        *part = NotePart::new(dimension, degree)?;
+        Some(())
+    }
+
+    /// Transposes `self` by `rhs`, adding the exponents of both harmonyms.
+    /// Returns `None` if the result has no name.
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let (lhs, rhs) = (self.exponents(), rhs.exponents());
+        Self::from_exponents(std::array::from_fn(|idx| lhs[idx] + rhs[idx]))
+    }
+
+    /// The interval from `rhs` up to `self`, subtracting the exponents of `rhs`.
+    /// Returns `None` if the interval has no name.
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        self.checked_mul(rhs.inv())
+    }
+
+    /// Iterates over the components of this harmonym, one per dimension.
+    pub fn parts(&self) -> impl Iterator<Item = NotePart> {
+        self.notes.into_iter()
+    }
+
+    /// Exponents over the primes 2, 3, 5, 7 and 11, in that order.
+    pub fn prime_exponents(&self) -> [i32; 5] {
+        let [octave, three, five, seven, eleven] = self.exponents().map(i32::from);
+        [
+            octave - three - 2 * (five + seven + eleven),
+            three,
+            five,
+            seven,
+            eleven,
+        ]
+    }
+
+    /// Size of the interval above the unison in cents.
+    pub fn cents(&self) -> f32 {
+        self.prime_exponents()
+            .into_iter()
+            .zip(PRIMES)
+            .map(|(exp, prime)| exp as f32 * 1200. * (prime as f32).log2())
+            .sum()
+    }
+
+    /// Tenney height, `log2(n * d)` of the reduced ratio. Used as the complexity measure.
+    pub fn tenney_height(&self) -> f32 {
+        self.prime_exponents()
+            .into_iter()
+            .zip(PRIMES)
+            .map(|(exp, prime)| exp.unsigned_abs() as f32 * (prime as f32).log2())
+            .sum()
+    }
+
+    /// Odd limit, the larger odd part of the numerator and denominator.
+    pub fn odd_limit(&self) -> u64 {
+        let (mut num, mut den) = (1u64, 1u64);
+        for (exp, prime) in self.prime_exponents().into_iter().zip(PRIMES).skip(1) {
+            let power = prime.saturating_pow(exp.unsigned_abs());
+            if exp > 0 {
+                num = num.saturating_mul(power);
+            } else {
+                den = den.saturating_mul(power);
+            }
+        }
+        num.max(den)
+    }
+
     pub fn eval(&self) -> Ratio {
         let mut ratio = Ratio {
             divisor: 1,
@@ -228,9 +318,12 @@ impl Harmonym {
     }
 }
 
+/// Orders by pitch, breaking ties between enharmonic spellings by their exponents.
 impl Ord for Harmonym {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        Into::<f32>::into(self.eval()).total_cmp(&other.eval().into())
+        self.cents()
+            .total_cmp(&other.cents())
+            .then_with(|| self.exponents().cmp(&other.exponents()))
     }
 }
 
@@ -243,12 +336,46 @@ impl PartialOrd for Harmonym {
 impl Mul<f32> for Harmonym {
     type Output = f32;
     fn mul(self, rhs: f32) -> Self::Output {
-        let ratio = self.eval();
-        rhs * ratio.dividend as f32 / ratio.divisor as f32
+        rhs * (self.cents() / 1200.).exp2()
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Same as [`Harmonym::checked_mul`], `None` if the result lies beyond [`MAX_DEGREE`].
+impl Mul for Harmonym {
+    type Output = Option<Harmonym>;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.checked_mul(rhs)
+    }
+}
+
+/// Same as [`Harmonym::checked_div`], `None` if the result lies beyond [`MAX_DEGREE`].
+impl Div for Harmonym {
+    type Output = Option<Harmonym>;
+    fn div(self, rhs: Self) -> Self::Output {
+        self.checked_div(rhs)
+    }
+}
+
+impl IntoIterator for Harmonym {
+    type Item = NotePart;
+    type IntoIter = std::array::IntoIter<NotePart, 5>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.notes.into_iter()
+    }
+}
+
+/// Inverts the harmonym around the unison, negating every exponent.
+impl Inv for Harmonym {
+    type Output = Harmonym;
+    fn inv(mut self) -> Self::Output {
+        for el in self.notes.iter_mut() {
+            el.degree = -el.degree;
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NotePart {
     dimension: u8,
     degree: i8,
@@ -349,9 +476,8 @@ impl NotePart {
     pub fn degree(&self) -> i8 {
         self.degree
     }
-    #[allow(unused)]
     pub fn new(dimension: u8, degree: i8) -> Option<Self> {
-        if degree < 4 && degree > -4 && dimension <= 5 {
+        if (-MAX_DEGREE..=MAX_DEGREE).contains(&degree) && dimension <= 5 {
             Some(NotePart { dimension, degree })
         } else {
             None
@@ -630,16 +756,21 @@ pub fn parse_harmonym(input: &str) -> IResult<&str, Harmonym> {
         }
     }
 
-    let (next, parts) = many0(char('+')).parse(rest)?;
-    notes[0] = NotePart {
-        degree: parts.len() as i8,
-        dimension: 1,
-    };
-    rest = next;
-    let (next, parts) = many0(char('-')).parse(rest)?;
-    notes[0] = NotePart {
-        degree: -(parts.len() as i8),
-        dimension: 1,
+    let (next, up) = many0(char('+')).parse(rest)?;
+    let (next, down) = many0(char('-')).parse(next)?;
+    let octaves = i8::try_from(up.len().abs_diff(down.len())).ok();
+    notes[0] = match octaves.and_then(|el| NotePart::new(1, el)) {
+        Some(part) if up.len() >= down.len() => part,
+        Some(part) => NotePart {
+            degree: -part.degree,
+            dimension: 1,
+        },
+        None => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                rest,
+                ErrorKind::TooLarge,
+            )));
+        }
     };
     rest = next;
     if rest.is_empty() {
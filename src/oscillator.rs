@@ -0,0 +1,111 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    playable::{Bandlimit, Waveform},
+    wavetable::{self, Ladder, TABLE_SIZE},
+};
+
+/// A single voice of a [`Waveform`] at a fixed frequency, driven by a phase accumulator.
+pub struct Oscillator {
+    waveform: Waveform,
+    bandlimit: Bandlimit,
+    /// Band-limited tables from the [`wavetable`] cache, only used by [`Bandlimit::Additive`].
+    ladder: Option<Arc<Ladder>>,
+    /// Band of the ladder read at the current frequency.
+    band: usize,
+    sample_rate: u32,
+    /// Position inside the period, from 0 to 1.
+    phase: f32,
+    /// Phase advance per frame, `frequency / sample_rate`.
+    increment: f32,
+}
+
+impl Oscillator {
+    pub fn new(frequency: f32, waveform: Waveform, bandlimit: Bandlimit, sample_rate: u32) -> Self {
+        let ladder = match bandlimit {
+            Bandlimit::Additive => Some(wavetable::ladder(waveform, sample_rate)),
+            Bandlimit::Naive | Bandlimit::PolyBlep => None,
+        };
+        Self {
+            waveform,
+            bandlimit,
+            band: ladder.as_ref().map_or(0, |el| el.band(frequency)),
+            ladder,
+            sample_rate,
+            phase: 0.,
+            increment: frequency / sample_rate as f32,
+        }
+    }
+
+    /// Moves to `frequency` without resetting the phase.
+    ///
+    /// Only switches between tables the oscillator already holds, so it is safe to call every frame on the audio
+    /// thread.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.increment = frequency / self.sample_rate as f32;
+        if let Some(ladder) = &self.ladder {
+            self.band = ladder.band(frequency);
+        }
+    }
+
+    fn naive(&self, phase: f32) -> f32 {
+        match self.waveform {
+            Waveform::Sine => (2. * PI * phase).sin(),
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.
+                } else {
+                    -1.
+                }
+            }
+            Waveform::Saw => 2. * (phase + 0.5).fract() - 1.,
+            Waveform::Triangle => (2. / PI) * (2. * PI * phase).sin().asin(),
+        }
+    }
+
+    fn interpolated(&self, phase: f32) -> f32 {
+        let Some(ladder) = &self.ladder else {
+            return 0.;
+        };
+        let table = ladder.table(self.band);
+        let position = phase * TABLE_SIZE as f32;
+        let idx = position as usize % TABLE_SIZE;
+        let frac = position.fract();
+        table[idx] * (1. - frac) + table[(idx + 1) % TABLE_SIZE] * frac
+    }
+}
+
+impl Iterator for Oscillator {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let phase = self.phase;
+        let sample = match (self.bandlimit, self.waveform) {
+            (Bandlimit::Additive, _) => self.interpolated(phase),
+            (Bandlimit::PolyBlep, Waveform::Saw) => {
+                let shifted = (phase + 0.5).fract();
+                self.naive(phase) - poly_blep(shifted, self.increment)
+            }
+            (Bandlimit::PolyBlep, Waveform::Square) => {
+                self.naive(phase) + poly_blep(phase, self.increment)
+                    - poly_blep((phase + 0.5).fract(), self.increment)
+            }
+            _ => self.naive(phase),
+        };
+        self.phase = (self.phase + self.increment).fract();
+        Some(sample)
+    }
+}
+
+/// Polynomial correction around a unit step discontinuity at phase 0.
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let t = phase / increment;
+        2. * t - t * t - 1.
+    } else if phase > 1. - increment {
+        let t = (phase - 1.) / increment;
+        t * t + 2. * t + 1.
+    } else {
+        0.
+    }
+}
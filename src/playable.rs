@@ -1,13 +1,17 @@
-use std::{iter::Cycle, time::Duration, vec::IntoIter};
+use std::time::Duration;
 
 use rodio::Source;
 
 use crate::{
-    DEFAULT_BASE, STRUMMING,
     chord::{Chord, FullChord},
+    envelope::{Adsr, Envelope, ReleaseHandle},
+    instrument::{Generator, Instrument},
+    mix::{Meter, Mixer, pan},
+    modulation::{Modulation, Modulator, fresh_seed, voice_seed},
     note::Harmonym,
 };
 
+/// Sample rate used unless another one is set.
 pub const SAMPLE_RATE: u32 = 48000;
 
 pub const DEFAULT_WAVE: Waveform = Waveform::Triangle;
@@ -15,68 +19,161 @@ pub const DEFAULT_WAVE: Waveform = Waveform::Triangle;
 pub struct PlayableChord {
     harmonyms: Vec<Harmonym>,
     base: f32,
+    sample_rate: u32,
     current_sample: u32,
-    wavetype: Waveform,
-    iterators: Vec<Cycle<IntoIter<f32>>>,
-    strum: bool,
+    instrument: Instrument,
+    generators: Vec<Generator>,
+    options: PlayOptions,
+    /// Frame at which each voice starts sounding.
+    starts: Vec<u32>,
+    /// Stereo position of each voice, from -1 (left) to 1 (right).
+    pans: Vec<f32>,
+    /// Positions set with [`PlayableChord::set_pans`], overriding the spread of the options.
+    custom_pans: Option<Vec<f32>>,
+    envelope: Adsr,
+    envelopes: Vec<Envelope>,
+    modulation: Modulation,
+    modulators: Vec<Modulator>,
+    /// Seed set with [`PlayableChord::set_seed`]. Without one, every prerender draws a [`fresh_seed`].
+    seed: Option<u64>,
+    /// Time until the note-off set with [`PlayableChord::release_after`], and the frame it falls on.
+    hold: Option<Duration>,
+    note_off: Option<u32>,
+    release: ReleaseHandle,
+    mixer: Mixer,
+    /// Right channel of the current frame, returned after the left one.
+    pending_right: Option<f32>,
 }
 
 impl PlayableChord {
     pub fn prerender(&mut self) {
-        let mut cache = Vec::with_capacity(self.harmonyms.len());
-        for i in &self.harmonyms {
-            let mut chart: Vec<f32> = Vec::with_capacity(
-                (SAMPLE_RATE / ((Into::<f32>::into(i.eval()) * DEFAULT_BASE) as u32)) as usize,
-            );
-            let mut t: f32;
-            for j in 0..SAMPLE_RATE / ((Into::<f32>::into(i.eval()) * DEFAULT_BASE) as u32) {
-                t = j as f32 / self.sample_rate() as f32;
-                let phase: f32 = 2.0 * std::f32::consts::PI * (*i * self.base) * t;
-                let sample = match self.wavetype {
-                    Waveform::Sine => phase.sin(),
-                    Waveform::Square => {
-                        if phase.sin() >= 0.0 {
-                            1.0
-                        } else {
-                            -1.0
-                        }
-                    }
-                    Waveform::Saw => {
-                        2.0 * (phase / (2.0 * std::f32::consts::PI)
-                            - ((phase / (2.0 * std::f32::consts::PI)) + 0.5).floor())
-                    }
-                    Waveform::Triangle => (2.0 / std::f32::consts::PI) * (phase * 0.5).sin().asin(),
-                };
-                chart.push(sample);
-            }
-            cache.push(chart);
-        }
-        self.iterators = cache.into_iter().map(|el| el.into_iter().cycle()).collect();
+        let seed = self.seed.unwrap_or_else(fresh_seed);
+        self.generators = self
+            .harmonyms
+            .iter()
+            .enumerate()
+            .map(|(idx, el)| {
+                self.instrument.generator(
+                    *el * self.base,
+                    self.sample_rate(),
+                    voice_seed(*el, idx) ^ seed,
+                )
+            })
+            .collect();
+        self.envelopes = self
+            .harmonyms
+            .iter()
+            .map(|_| Envelope::new(self.envelope, self.sample_rate()))
+            .collect();
+        self.modulators = self
+            .harmonyms
+            .iter()
+            .enumerate()
+            .map(|(idx, el)| {
+                Modulator::new(
+                    &self.modulation,
+                    voice_seed(*el, idx) ^ seed,
+                    self.sample_rate(),
+                )
+            })
+            .collect();
+        self.starts = self.options.starts(&self.harmonyms, self.sample_rate());
+        self.pans = match &self.custom_pans {
+            Some(pans) => (0..self.harmonyms.len())
+                .map(|el| pans.get(el).copied().unwrap_or(0.))
+                .collect(),
+            None => self.options.spread.pans(&self.harmonyms),
+        };
+        self.mixer.set_voices(self.harmonyms.len());
+    }
+    /// Takes effect on the next [`PlayableChord::prerender`].
+    pub fn set_instrument(&mut self, instrument: impl Into<Instrument>) {
+        self.instrument = instrument.into();
+    }
+    /// Places each voice at a stereo position from -1 (left) to 1 (right), in the order of the chord's tones.
+    /// Takes effect on the next [`PlayableChord::prerender`].
+    pub fn set_pans(&mut self, pans: Vec<f32>) {
+        self.custom_pans = Some(pans);
+    }
+    /// Sets the envelope of every voice. Takes effect on the next [`PlayableChord::prerender`].
+    pub fn set_envelope(&mut self, envelope: Adsr) {
+        self.envelope = envelope;
+    }
+    /// Sets the vibrato and tremolo of every voice. Takes effect on the next [`PlayableChord::prerender`].
+    pub fn set_modulation(&mut self, modulation: Modulation) {
+        self.modulation = modulation;
+    }
+    /// Fixes the LFO phases and noise of the voices, so every prerender plays the same way. Takes effect on the next
+    /// [`PlayableChord::prerender`].
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+    /// Sends a note-off once `duration` has been played. The chord ends after the release.
+    pub fn release_after(&mut self, duration: Duration) {
+        self.hold = Some(duration);
+        self.note_off = Some(self.frames(duration));
+    }
+    /// Renders at `sample_rate` instead of [`SAMPLE_RATE`]. Takes effect on the next [`PlayableChord::prerender`].
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.note_off = self.hold.map(|el| self.frames(el));
+        self.mixer.set_sample_rate(sample_rate);
+    }
+    fn frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * self.sample_rate as f64) as u32
+    }
+    /// Output level, updated while the chord plays.
+    pub fn meter(&self) -> Meter {
+        self.mixer.meter()
+    }
+    /// A handle to send a note-off while the chord is playing, e.g. from inside a `Sink`.
+    pub fn release_handle(&self) -> ReleaseHandle {
+        self.release.clone()
     }
 }
 
 impl From<FullChord> for PlayableChord {
     fn from(value: FullChord) -> Self {
-        Self {
-            harmonyms: value.tones,
-            base: value.base,
-            current_sample: 0,
-            wavetype: DEFAULT_WAVE,
-            iterators: Vec::new(),
-            strum: STRUMMING.load(std::sync::atomic::Ordering::Relaxed),
-        }
+        (value, PlayOptions::default()).into()
     }
 }
 
 impl From<Chord> for PlayableChord {
     fn from(value: Chord) -> Self {
+        FullChord::from(value).into()
+    }
+}
+
+impl From<(Chord, PlayOptions)> for PlayableChord {
+    fn from(value: (Chord, PlayOptions)) -> Self {
+        (FullChord::from(value.0), value.1).into()
+    }
+}
+
+impl From<(FullChord, PlayOptions)> for PlayableChord {
+    fn from(value: (FullChord, PlayOptions)) -> Self {
+        let (chord, options) = value;
         Self {
-            harmonyms: value.tones,
-            base: DEFAULT_BASE,
+            harmonyms: chord.tones,
+            base: chord.base,
+            sample_rate: SAMPLE_RATE,
             current_sample: 0,
-            wavetype: DEFAULT_WAVE,
-            iterators: Vec::new(),
-            strum: STRUMMING.load(std::sync::atomic::Ordering::Relaxed),
+            instrument: Instrument::default(),
+            generators: Vec::new(),
+            options,
+            starts: Vec::new(),
+            pans: Vec::new(),
+            custom_pans: None,
+            envelope: Adsr::default(),
+            envelopes: Vec::new(),
+            modulation: Modulation::default(),
+            modulators: Vec::new(),
+            seed: None,
+            hold: None,
+            note_off: None,
+            release: ReleaseHandle::default(),
+            mixer: Mixer::new(SAMPLE_RATE),
+            pending_right: None,
         }
     }
 }
@@ -85,24 +182,45 @@ impl Iterator for PlayableChord {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.strum {
-            self.current_sample += 1;
-            Some(
-                self.iterators
-                    .iter_mut()
-                    .enumerate()
-                    .map(|(idx, el)| {
-                        if (idx as f32) > self.current_sample as f32 / SAMPLE_RATE as f32 * 15. {
-                            0.
-                        } else {
-                            el.next().unwrap()
-                        }
-                    })
-                    .sum(),
-            )
-        } else {
-            Some(self.iterators.iter_mut().map(|el| el.next().unwrap()).sum())
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        if self.release.is_released() || self.note_off.is_some_and(|el| self.current_sample >= el) {
+            self.envelopes.iter_mut().for_each(Envelope::note_off);
         }
+        if !self.envelopes.is_empty() && self.envelopes.iter().all(Envelope::is_finished) {
+            return None;
+        }
+        let frame = self.current_sample;
+        self.current_sample += 1;
+        let sum = self
+            .generators
+            .iter_mut()
+            .zip(self.envelopes.iter_mut().zip(self.modulators.iter_mut()))
+            .zip(
+                self.harmonyms
+                    .iter()
+                    .zip(self.starts.iter().zip(&self.pans)),
+            )
+            .map(
+                |((wave, (envelope, modulator)), (harmonym, (start, position)))| {
+                    if frame < *start {
+                        return [0.; 2];
+                    }
+                    let (ratio, gain) = modulator.next().unwrap();
+                    if modulator.has_vibrato() {
+                        wave.set_frequency(*harmonym * self.base * ratio);
+                    }
+                    pan(
+                        wave.next().unwrap() * envelope.next().unwrap() * gain,
+                        *position,
+                    )
+                },
+            )
+            .fold([0.; 2], |acc, el| [acc[0] + el[0], acc[1] + el[1]]);
+        let [left, right] = self.mixer.mix(sum, self.generators.len());
+        self.pending_right = Some(right);
+        Some(left)
         /*
         let t = self.current_sample as f32 / SAMPLE_RATE as f32;
         let mut total = 0.;
@@ -138,20 +256,180 @@ impl Source for PlayableChord {
     }
 
     fn channels(&self) -> u16 {
-        1
+        2
     }
 
     fn sample_rate(&self) -> u32 {
-        SAMPLE_RATE
+        self.sample_rate
     }
 
     fn total_duration(&self) -> Option<Duration> {
         None
     }
 }
+/// How a chord is articulated, set per chord when it is converted into a [`PlayableChord`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayOptions {
+    /// Start the voices one after another instead of all at once.
+    pub strum: Option<Strum>,
+    /// How the voices are placed in the stereo field.
+    pub spread: Spread,
+}
+
+impl PlayOptions {
+    /// Moves on to the next chord played with these options, flipping an alternating strum.
+    pub fn advance(&mut self) {
+        if let Some(strum) = &mut self.strum {
+            strum.stroke += 1;
+        }
+    }
+
+    /// Frame at which each of `harmonyms` starts sounding.
+    pub fn starts(&self, harmonyms: &[Harmonym], sample_rate: u32) -> Vec<u32> {
+        let mut starts = vec![0; harmonyms.len()];
+        let Some(strum) = self.strum else {
+            return starts;
+        };
+        let mut order: Vec<usize> = (0..harmonyms.len()).collect();
+        order.sort_by_key(|el| harmonyms[*el]);
+        if strum.is_downstroke() {
+            order.reverse();
+        }
+        let (rate, curve) = strum.checked();
+        let last = harmonyms.len().saturating_sub(1).max(1) as f32;
+        let length = last / rate * sample_rate as f32;
+        for (position, idx) in order.into_iter().enumerate() {
+            starts[idx] = (length * (position as f32 / last).powf(curve)) as u32;
+        }
+        starts
+    }
+}
+
+/// Automatic stereo placement of the voices of a chord.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Spread {
+    /// Every voice in the middle.
+    #[default]
+    Center,
+    /// Low tones to the left and high tones to the right, spanning `width` (0 to 1) to each side.
+    Pitch(f32),
+    /// Each lattice dimension pulls a tone towards its own side, fifths and sevenths to the right, thirds and elevenths
+    /// to the left, so tones that differ by a comma still land apart. Spans `width` (0 to 1) to each side.
+    Lattice(f32),
+}
+
+/// How far one step along each dimension moves a tone for [`Spread::Lattice`]. The octave does not move it.
+const LATTICE_PAN: [f32; 5] = [0., 1., -0.7, 0.45, -0.3];
+
+impl Spread {
+    /// Stereo position of each of `harmonyms`.
+    pub fn pans(&self, harmonyms: &[Harmonym]) -> Vec<f32> {
+        let (positions, width): (Vec<f32>, f32) = match self {
+            Self::Center => return vec![0.; harmonyms.len()],
+            Self::Pitch(width) => (harmonyms.iter().map(Harmonym::cents).collect(), *width),
+            Self::Lattice(width) => (
+                harmonyms
+                    .iter()
+                    .map(|el| {
+                        el.exponents()
+                            .iter()
+                            .zip(LATTICE_PAN)
+                            .map(|(exp, weight)| *exp as f32 * weight)
+                            .sum()
+                    })
+                    .collect(),
+                *width,
+            ),
+        };
+        let low = positions.iter().copied().fold(f32::INFINITY, f32::min);
+        let high = positions.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        positions
+            .into_iter()
+            .map(|el| {
+                if high - low < 1e-6 {
+                    0.
+                } else {
+                    width * (2. * (el - low) / (high - low) - 1.)
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strum {
+    pub direction: StrumDirection,
+    /// Voices started per second, on average over the stroke. Must be positive, otherwise the default is used.
+    pub rate: f32,
+    /// Exponent shaping the delays over the stroke. 1 spaces the voices evenly,
+    /// above 1 starts them close together and spreads out the last ones, below 1 does the opposite.
+    /// Must be positive, otherwise the voices are spaced evenly.
+    pub curve: f32,
+    /// Chords already strummed with these settings, which decides the direction of an alternating strum.
+    pub stroke: u32,
+}
+
+impl Default for Strum {
+    fn default() -> Self {
+        Self {
+            direction: StrumDirection::Up,
+            rate: 15.,
+            curve: 1.,
+            stroke: 0,
+        }
+    }
+}
+
+impl Strum {
+    /// Rate and curve, replacing values that are not positive and finite with the defaults.
+    fn checked(&self) -> (f32, f32) {
+        let valid = |el: f32| el > 0. && el.is_finite();
+        let default = Self::default();
+        (
+            Some(self.rate)
+                .filter(|el| valid(*el))
+                .unwrap_or(default.rate),
+            Some(self.curve)
+                .filter(|el| valid(*el))
+                .unwrap_or(default.curve),
+        )
+    }
+
+    fn is_downstroke(&self) -> bool {
+        match self.direction {
+            StrumDirection::Up => false,
+            StrumDirection::Down => true,
+            StrumDirection::Alternating => self.stroke % 2 == 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrumDirection {
+    /// Lowest tone first.
+    Up,
+    /// Highest tone first.
+    Down,
+    /// Up, then down on the next chord, and so on.
+    Alternating,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Waveform {
     Sine,
     Square,
     Saw,
     Triangle,
 }
+
+/// How a [`Waveform`] is kept from aliasing at high pitches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Bandlimit {
+    /// The ideal waveform sampled directly. Aliases above a few hundred Hz.
+    Naive,
+    /// Fourier series truncated below Nyquist, read from a wavetable.
+    #[default]
+    Additive,
+    /// Naive waveform with polynomial corrections at its discontinuities. Only affects `Square` and `Saw`.
+    PolyBlep,
+}
@@ -0,0 +1,226 @@
+use std::{thread, time::Duration};
+
+use rodio::Source;
+
+use crate::{
+    effects::{self, BoxedSource},
+    mix::{Meter, Mixer},
+    score::{Score, ScoreVoice},
+};
+
+/// Frames rendered per block unless set otherwise.
+pub const DEFAULT_BLOCK_FRAMES: usize = 4096;
+
+/// Voices summed into one buffer before the buffers are added up. The grouping depends only on the score, never on
+/// the number of threads, so the floating-point sums always happen in the same order.
+const GROUP_VOICES: usize = 8;
+
+/// Stereo sum of a few neighbouring voices over one block.
+#[derive(Default)]
+struct Group {
+    samples: Vec<f32>,
+    /// Voices sounding on each frame.
+    voices: Vec<u32>,
+}
+
+impl Group {
+    fn render(&mut self, voices: &mut [ScoreVoice], first: u32, frames: usize) {
+        self.samples.clear();
+        self.samples.resize(frames * 2, 0.);
+        self.voices.clear();
+        self.voices.resize(frames, 0);
+        for voice in voices {
+            for idx in 0..frames {
+                let frame = first + idx as u32;
+                if frame < voice.start() {
+                    continue;
+                }
+                if voice.is_finished() {
+                    break;
+                }
+                let [left, right] = voice.render(frame);
+                self.samples[idx * 2] += left;
+                self.samples[idx * 2 + 1] += right;
+                self.voices[idx] += 1;
+            }
+        }
+    }
+}
+
+/// Renders a [`Score`] a block at a time, spreading the voices of each block over several threads.
+///
+/// Only the voices sounding in the current block are kept, so memory stays bounded however long the score is. The
+/// output is the same for any number of threads. It can be read block by block with [`BlockRenderer::next_block`]
+/// or sample by sample as a [`Source`], and sounds like the [`crate::score::PlayableScore`] of the same score up to
+/// rounding.
+pub struct BlockRenderer {
+    score: Score,
+    threads: usize,
+    block_frames: usize,
+    next_note: usize,
+    voices: Vec<ScoreVoice>,
+    groups: Vec<Group>,
+    /// First frame of the next block.
+    frame: u32,
+    mixer: Mixer,
+    /// Interleaved stereo output of the current block.
+    block: Vec<f32>,
+    /// Samples of the block already returned by the iterator.
+    position: usize,
+    finished: bool,
+}
+
+impl From<Score> for BlockRenderer {
+    fn from(mut score: Score) -> Self {
+        score.sort();
+        Self {
+            mixer: Mixer::new(score.sample_rate()),
+            score,
+            threads: thread::available_parallelism().map_or(1, usize::from),
+            block_frames: DEFAULT_BLOCK_FRAMES,
+            next_note: 0,
+            voices: Vec::new(),
+            groups: Vec::new(),
+            frame: 0,
+            block: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+}
+
+impl BlockRenderer {
+    /// Threads the voices are spread over. Defaults to the number of cores.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+    /// Frames per block. Larger blocks spend less time starting threads but hold more memory per voice group.
+    pub fn set_block_frames(&mut self, frames: usize) {
+        self.block_frames = frames.max(1);
+    }
+    /// Output level, updated while the score renders.
+    pub fn meter(&self) -> Meter {
+        self.mixer.meter()
+    }
+    /// Runs the output through the effects of the score. Take the [`BlockRenderer::meter`] first if it is needed.
+    pub fn with_effects(self) -> BoxedSource {
+        let effects = self.score.effects().to_vec();
+        effects::chain(self, &effects)
+    }
+
+    /// Renders the next block of interleaved stereo samples, or returns `None` once the score has ended. The last
+    /// block may be shorter.
+    pub fn next_block(&mut self) -> Option<&[f32]> {
+        if !self.render() {
+            return None;
+        }
+        self.position = self.block.len();
+        Some(&self.block)
+    }
+
+    fn render(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+        let first = self.frame;
+        let end = first.saturating_add(self.block_frames as u32);
+        let notes = self.score.notes();
+        while self.next_note < notes.len()
+            && self.score.frame(notes[self.next_note].duration.start) < end
+        {
+            self.voices
+                .push(ScoreVoice::new(&self.score, self.next_note));
+            self.next_note += 1;
+        }
+        let pending = self.next_note < self.score.notes().len();
+        if self.voices.is_empty() && !pending {
+            self.finished = true;
+            return false;
+        }
+
+        let frames = (end - first) as usize;
+        let groups = self.voices.len().div_ceil(GROUP_VOICES);
+        self.groups.resize_with(groups, Group::default);
+        let per_thread = groups.div_ceil(self.threads).max(1);
+        let shares = self
+            .voices
+            .chunks_mut(per_thread * GROUP_VOICES)
+            .zip(self.groups.chunks_mut(per_thread));
+        let render = |(voices, groups): (&mut [ScoreVoice], &mut [Group])| {
+            for (voices, group) in voices.chunks_mut(GROUP_VOICES).zip(groups) {
+                group.render(voices, first, frames);
+            }
+        };
+        if groups <= per_thread {
+            shares.for_each(render);
+        } else {
+            thread::scope(|scope| {
+                for share in shares {
+                    scope.spawn(move || render(share));
+                }
+            });
+        }
+
+        let mut sum = vec![0.; frames * 2];
+        let mut voices = vec![0; frames];
+        for group in &self.groups {
+            sum.iter_mut()
+                .zip(&group.samples)
+                .for_each(|(acc, el)| *acc += el);
+            voices
+                .iter_mut()
+                .zip(&group.voices)
+                .for_each(|(acc, el)| *acc += el);
+        }
+        self.voices.retain(|el| !el.is_finished());
+        // With nothing left to start or sounding on, the score ends after the last frame anything sounded on.
+        let length = if pending || !self.voices.is_empty() {
+            frames
+        } else {
+            voices.iter().rposition(|el| *el > 0).map_or(0, |el| el + 1)
+        };
+        if length == 0 {
+            self.finished = true;
+            return false;
+        }
+
+        self.block.clear();
+        for (frame, count) in sum.chunks(2).zip(&voices).take(length) {
+            self.block
+                .extend(self.mixer.mix([frame[0], frame[1]], *count as usize));
+        }
+        self.frame = end;
+        self.position = 0;
+        true
+    }
+}
+
+impl Iterator for BlockRenderer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.block.len() && !self.render() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.block[self.position - 1])
+    }
+}
+
+impl Source for BlockRenderer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.score.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,193 @@
+use std::{collections::VecDeque, f64::consts::PI, time::Duration};
+
+use rodio::Source;
+
+use crate::effects::BoxedSource;
+
+/// How much work goes into converting between sample rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Quality {
+    /// Short kernel for quick previews. Some aliasing near Nyquist.
+    Draft,
+    #[default]
+    Standard,
+    /// Long kernel with a steep cutoff, for final bounces.
+    High,
+}
+
+impl Quality {
+    /// Zero crossings of the interpolation kernel on each side.
+    pub fn zero_crossings(self) -> usize {
+        match self {
+            Self::Draft => 4,
+            Self::Standard => 8,
+            Self::High => 32,
+        }
+    }
+}
+
+/// Hann-windowed sinc, spanning `width` frames on each side, with a cutoff at `cutoff` times Nyquist.
+pub(crate) fn kernel(x: f64, cutoff: f64, width: f64) -> f64 {
+    let window = 0.5 + 0.5 * (PI * x / width).cos();
+    cutoff * sinc(x * cutoff) * window
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.
+    } else {
+        let x = PI * x;
+        x.sin() / x
+    }
+}
+
+/// Converts `source` to `sample_rate`, or passes it through if it already runs at that rate.
+pub fn resample(source: BoxedSource, sample_rate: u32, quality: Quality) -> BoxedSource {
+    if source.sample_rate() == sample_rate {
+        source
+    } else {
+        Box::new(Resample::new(source, sample_rate, quality))
+    }
+}
+
+/// Band-limited sample rate conversion of an interleaved source by windowed-sinc interpolation.
+///
+/// The rate of the source is read once, so sources that change rate while playing are not followed.
+pub struct Resample<S> {
+    source: S,
+    channels: usize,
+    sample_rate: u32,
+    /// Source frames advanced per output frame.
+    step: f64,
+    cutoff: f64,
+    /// Kernel frames on each side.
+    width: i64,
+    /// Interleaved source frames still under the kernel.
+    history: VecDeque<f32>,
+    /// Index of the first frame in `history`.
+    offset: i64,
+    /// Read position in source frames.
+    position: f64,
+    ended: bool,
+    /// The output frame being returned, one channel at a time.
+    frame: Vec<f32>,
+    channel: usize,
+}
+
+impl<S: Source<Item = f32>> Resample<S> {
+    /// Panics if `sample_rate` or the rate of `source` is 0, since no frame would ever advance the other.
+    pub fn new(source: S, sample_rate: u32, quality: Quality) -> Self {
+        assert!(
+            sample_rate > 0 && source.sample_rate() > 0,
+            "sample rate must not be 0"
+        );
+        let channels = source.channels().max(1) as usize;
+        let step = source.sample_rate() as f64 / sample_rate as f64;
+        // Lower the cutoff when the rate goes down so the conversion does not alias.
+        let cutoff = (1. / step).min(1.);
+        Self {
+            source,
+            channels,
+            sample_rate,
+            step,
+            cutoff,
+            width: (quality.zero_crossings() as f64 / cutoff).ceil() as i64,
+            history: VecDeque::new(),
+            offset: 0,
+            position: 0.,
+            ended: false,
+            frame: vec![0.; channels],
+            channel: channels,
+        }
+    }
+
+    fn loaded(&self) -> i64 {
+        self.offset + (self.history.len() / self.channels) as i64
+    }
+
+    /// Reads source frames until frame `last` is loaded or the source ends.
+    fn load(&mut self, last: i64) {
+        while !self.ended && self.loaded() <= last {
+            for channel in 0..self.channels {
+                match self.source.next() {
+                    Some(o) => self.history.push_back(o),
+                    None => {
+                        self.ended = true;
+                        // Pad the frame the source stopped in so the history stays whole frames.
+                        if channel > 0 {
+                            self.history.extend(vec![0.; self.channels - channel]);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn sample(&self, frame: i64, channel: usize) -> f32 {
+        if frame < self.offset {
+            return 0.;
+        }
+        let idx = (frame - self.offset) as usize * self.channels + channel;
+        self.history.get(idx).copied().unwrap_or(0.)
+    }
+
+    /// Computes the next output frame, or returns `false` once the source is used up.
+    fn render(&mut self) -> bool {
+        let center = self.position.floor() as i64;
+        self.load(center + self.width);
+        if self.ended && self.position >= self.loaded() as f64 {
+            return false;
+        }
+        let frac = self.position - center as f64;
+        self.frame.iter_mut().for_each(|el| *el = 0.);
+        for offset in 1 - self.width..=self.width {
+            let gain = kernel(offset as f64 - frac, self.cutoff, self.width as f64);
+            for channel in 0..self.channels {
+                self.frame[channel] += self.sample(center + offset, channel) * gain as f32;
+            }
+        }
+        self.position += self.step;
+
+        // Forget the frames that have left the kernel.
+        let first = self.position.floor() as i64 + 1 - self.width;
+        while self.offset < first && !self.history.is_empty() {
+            self.history.drain(..self.channels.min(self.history.len()));
+            self.offset += 1;
+        }
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Resample<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == self.channels {
+            if !self.render() {
+                return None;
+            }
+            self.channel = 0;
+        }
+        self.channel += 1;
+        Some(self.frame[self.channel - 1])
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Resample<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
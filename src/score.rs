@@ -1,43 +1,335 @@
 use std::time::Duration;
 
-use crate::{DEFAULT_BASE, note::Harmonym, playable::SAMPLE_RATE};
+use rodio::Source;
 
+use crate::{
+    DEFAULT_BASE,
+    effects::{self, BoxedSource, Effect},
+    envelope::{Adsr, Envelope},
+    glide::{Glide, Gliding},
+    instrument::{Generator, Instrument},
+    mix::{Meter, Mixer, pan},
+    modulation::{Modulation, Modulator, voice_seed},
+    note::Harmonym,
+    playable::SAMPLE_RATE,
+    time::{TempoMap, Ticks},
+};
+
+/// Notes placed in musical time. They are only turned into frames, through the tempo map, when the score is
+/// rendered.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Score {
     sample_rate: u32,
-    total_frames: u32,
-    notes: Vec<(NoteDuration, Harmonym)>,
+    tempo: TempoMap,
+    base: f32,
+    instrument: Instrument,
+    effects: Vec<Effect>,
+    notes: Vec<ScoreNote>,
 }
 
-impl Into<PlayableScore> for Score {
-    fn into(self) -> PlayableScore {
+impl From<Score> for PlayableScore {
+    fn from(mut score: Score) -> Self {
+        score.sort();
         PlayableScore {
-            score: self,
+            mixer: Mixer::new(score.sample_rate),
+            score,
             current_frame: 0,
-            base: DEFAULT_BASE,
+            next_note: 0,
+            voices: Vec::new(),
+            pending_right: None,
         }
     }
 }
 
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Score {
-    pub fn push(&mut self, note: (NoteDuration, Harmonym)) {
-        self.notes.push(note);
+    pub fn push(&mut self, note: impl Into<ScoreNote>) {
+        self.notes.push(note.into());
     }
     pub fn new() -> Self {
+        Self::with_sample_rate(SAMPLE_RATE)
+    }
+    /// Panics if `sample_rate` is 0.
+    pub fn with_sample_rate(sample_rate: u32) -> Self {
+        assert!(sample_rate > 0, "sample rate must not be 0");
         Self {
-            sample_rate: SAMPLE_RATE,
-            total_frames: 0,
+            sample_rate,
+            tempo: TempoMap::default(),
+            base: DEFAULT_BASE,
+            instrument: Instrument::default(),
+            effects: Vec::new(),
             notes: vec![],
         }
     }
+    pub fn notes(&self) -> &[ScoreNote] {
+        &self.notes
+    }
+    pub fn notes_mut(&mut self) -> &mut [ScoreNote] {
+        &mut self.notes
+    }
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    /// Panics if `sample_rate` is 0.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        assert!(sample_rate > 0, "sample rate must not be 0");
+        self.sample_rate = sample_rate;
+    }
+    pub fn tempo(&self) -> &TempoMap {
+        &self.tempo
+    }
+    pub fn tempo_mut(&mut self) -> &mut TempoMap {
+        &mut self.tempo
+    }
+    pub fn set_tempo(&mut self, tempo: TempoMap) {
+        self.tempo = tempo;
+    }
+    pub fn base(&self) -> f32 {
+        self.base
+    }
+    pub fn set_base(&mut self, base: f32) {
+        self.base = base;
+    }
+    pub fn instrument(&self) -> &Instrument {
+        &self.instrument
+    }
+    pub fn set_instrument(&mut self, instrument: impl Into<Instrument>) {
+        self.instrument = instrument.into();
+    }
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+    /// Effects the whole score is run through, in order. Applied by [`PlayableScore::with_effects`].
+    pub fn set_effects(&mut self, effects: Vec<Effect>) {
+        self.effects = effects;
+    }
+    /// Tick at which the last note is released.
+    pub fn length(&self) -> Ticks {
+        self.notes
+            .iter()
+            .map(|el| el.duration.end())
+            .max()
+            .unwrap_or(0)
+    }
+    /// Frame at which the last note is released.
+    pub fn total_frames(&self) -> u32 {
+        self.frame(self.length())
+    }
+    /// Frame `at` falls on at the sample rate of the score.
+    pub fn frame(&self, at: Ticks) -> u32 {
+        self.tempo.frame(at, self.sample_rate)
+    }
+    /// Puts the notes in the order they start, which is the order they are rendered in.
+    pub(crate) fn sort(&mut self) {
+        self.notes.sort_by_key(|el| el.duration.start);
+    }
 }
 
+/// When a note sounds, in musical time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NoteDuration {
-    pub start: u32,
-    pub dur: Duration,
+    pub start: Ticks,
+    pub length: Ticks,
+}
+
+impl NoteDuration {
+    /// Tick the note is released on.
+    pub fn end(&self) -> Ticks {
+        self.start + self.length
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreNote {
+    pub duration: NoteDuration,
+    pub harmonym: Harmonym,
+    pub envelope: Adsr,
+    /// Stereo position from -1 (left) to 1 (right).
+    pub pan: f32,
+    /// Pitch movement, counted from the start of the note.
+    pub glide: Option<Glide>,
+    pub modulation: Modulation,
+    /// Frequency in Hz of the unison for this note, instead of the base of the score.
+    pub base: Option<f32>,
+}
+
+impl From<(NoteDuration, Harmonym)> for ScoreNote {
+    fn from(value: (NoteDuration, Harmonym)) -> Self {
+        Self {
+            duration: value.0,
+            harmonym: value.1,
+            envelope: Adsr::default(),
+            pan: 0.,
+            glide: None,
+            modulation: Modulation::default(),
+            base: None,
+        }
+    }
+}
+
+impl From<(NoteDuration, Harmonym, Adsr)> for ScoreNote {
+    fn from(value: (NoteDuration, Harmonym, Adsr)) -> Self {
+        Self {
+            duration: value.0,
+            harmonym: value.1,
+            envelope: value.2,
+            pan: 0.,
+            glide: None,
+            modulation: Modulation::default(),
+            base: None,
+        }
+    }
+}
+
+/// A note of a [`Score`] while it sounds.
+pub(crate) struct ScoreVoice {
+    start: u32,
+    /// Frequency of the unison harmonym.
+    base: f32,
+    wave: Generator,
+    envelope: Envelope,
+    note_off: u32,
+    pan: f32,
+    /// Frequency ratio to the unison the voice is sounding, before vibrato.
+    pitch: f32,
+    glide: Option<Gliding>,
+    modulator: Modulator,
+}
+
+impl ScoreVoice {
+    /// Starts the `idx`th note of `score`.
+    pub(crate) fn new(score: &Score, idx: usize) -> Self {
+        let note = &score.notes[idx];
+        let base = note.base.unwrap_or(score.base);
+        Self {
+            start: score.frame(note.duration.start),
+            base,
+            wave: score.instrument.generator(
+                note.harmonym * base,
+                score.sample_rate,
+                voice_seed(note.harmonym, idx),
+            ),
+            envelope: Envelope::new(note.envelope, score.sample_rate),
+            note_off: score.frame(note.duration.end()),
+            pan: note.pan,
+            pitch: note.harmonym * 1.,
+            glide: note
+                .glide
+                .map(|el| Gliding::new(&el, note.harmonym, score.sample_rate)),
+            modulator: Modulator::new(
+                &note.modulation,
+                voice_seed(note.harmonym, idx),
+                score.sample_rate,
+            ),
+        }
+    }
+
+    /// Frame the voice starts sounding on.
+    pub(crate) fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.envelope.is_finished()
+    }
+
+    /// Renders `frame` of the score, which must come right after the last one rendered.
+    pub(crate) fn render(&mut self, frame: u32) -> [f32; 2] {
+        if frame >= self.note_off {
+            self.envelope.note_off();
+        }
+        let (vibrato, gain) = self.modulator.next().unwrap();
+        if let Some(glide) = &mut self.glide {
+            match glide.next() {
+                Some(ratio) => {
+                    self.pitch = ratio;
+                    self.wave.set_frequency(ratio * self.base * vibrato);
+                }
+                None => self.glide = None,
+            }
+        } else if self.modulator.has_vibrato() {
+            self.wave.set_frequency(self.pitch * self.base * vibrato);
+        }
+        pan(
+            self.wave.next().unwrap() * self.envelope.next().unwrap() * gain,
+            self.pan,
+        )
+    }
 }
 
 pub struct PlayableScore {
     score: Score,
     current_frame: u32,
-    base: f32,
+    next_note: usize,
+    voices: Vec<ScoreVoice>,
+    mixer: Mixer,
+    /// Right channel of the current frame, returned after the left one.
+    pending_right: Option<f32>,
+}
+
+impl PlayableScore {
+    /// Output level, updated while the score plays.
+    pub fn meter(&self) -> Meter {
+        self.mixer.meter()
+    }
+    /// Runs the score through its effects. Take the [`PlayableScore::meter`] first if it is needed.
+    pub fn with_effects(mut self) -> BoxedSource {
+        let effects = std::mem::take(&mut self.score.effects);
+        effects::chain(self, &effects)
+    }
+}
+
+impl Iterator for PlayableScore {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+        while let Some(note) = self.score.notes.get(self.next_note) {
+            if self.score.frame(note.duration.start) > self.current_frame {
+                break;
+            }
+            self.voices
+                .push(ScoreVoice::new(&self.score, self.next_note));
+            self.next_note += 1;
+        }
+        self.voices.retain(|el| !el.is_finished());
+        if self.voices.is_empty() && self.next_note >= self.score.notes.len() {
+            return None;
+        }
+        let mut total = [0.; 2];
+        for voice in &mut self.voices {
+            let [left, right] = voice.render(self.current_frame);
+            total[0] += left;
+            total[1] += right;
+        }
+        self.current_frame += 1;
+        let [left, right] = self.mixer.mix(total, self.voices.len());
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for PlayableScore {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.score.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
 }
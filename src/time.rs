@@ -0,0 +1,271 @@
+use std::fmt;
+
+/// A point or a length in musical time. A quarter note lasts [`TICKS_PER_QUARTER`] ticks.
+pub type Ticks = u32;
+
+/// Resolution of musical time, divisible by 2, 3, 5 and 64 so tuplets and short notes land on whole ticks.
+pub const TICKS_PER_QUARTER: Ticks = 960;
+
+pub const DEFAULT_BPM: f64 = 120.;
+
+/// Length of `numerator / denominator` of a whole note, e.g. `note_length(3, 8)` for a dotted quarter.
+pub const fn note_length(numerator: u32, denominator: u32) -> Ticks {
+    4 * TICKS_PER_QUARTER * numerator / denominator
+}
+
+/// Most beats in a bar. Far beyond any real meter, and low enough that a bar of whole-note beats fits in [`Ticks`].
+pub const MAX_BEATS: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSignature {
+    /// Beats in a bar.
+    pub beats: u32,
+    /// Note value of a beat, 4 for quarter notes.
+    pub unit: u32,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { beats: 4, unit: 4 }
+    }
+}
+
+impl TimeSignature {
+    pub fn beat_ticks(&self) -> Ticks {
+        note_length(1, self.unit.max(1))
+    }
+
+    pub fn bar_ticks(&self) -> Ticks {
+        self.beat_ticks() * self.beats
+    }
+}
+
+/// A point in musical time as written in a score. Bars and beats count from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub bar: u32,
+    pub beat: u32,
+    /// Ticks past the beat.
+    pub tick: Ticks,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            bar: 1,
+            beat: 1,
+            tick: 0,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{:03}", self.bar, self.beat, self.tick)
+    }
+}
+
+/// A tempo taking over at a point in the score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    pub at: Ticks,
+    /// Quarter notes per minute.
+    pub bpm: f64,
+    /// Moves steadily to the tempo of the next change instead of holding until it.
+    pub ramp: bool,
+}
+
+/// Tempo and time signature over the course of a score, which turns musical time into seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// Sorted by position, the first one at tick 0.
+    tempos: Vec<TempoChange>,
+    /// Bar each time signature starts on, sorted, the first one at bar 1.
+    signatures: Vec<(u32, TimeSignature)>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::new(DEFAULT_BPM)
+    }
+}
+
+impl TempoMap {
+    /// A steady `bpm` in 4/4.
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            tempos: vec![TempoChange {
+                at: 0,
+                bpm,
+                ramp: false,
+            }],
+            signatures: vec![(1, TimeSignature::default())],
+        }
+    }
+
+    pub fn tempos(&self) -> &[TempoChange] {
+        &self.tempos
+    }
+
+    pub fn time_signatures(&self) -> &[(u32, TimeSignature)] {
+        &self.signatures
+    }
+
+    /// Index of the last tempo change at or before `at`.
+    fn tempo_index(&self, at: Ticks) -> usize {
+        self.tempos
+            .partition_point(|el| el.at <= at)
+            .saturating_sub(1)
+    }
+
+    /// Switches to `bpm` at `at`, replacing any change already there.
+    pub fn set_tempo(&mut self, at: Ticks, bpm: f64) {
+        let change = TempoChange {
+            at,
+            bpm: bpm.max(1e-3),
+            ramp: false,
+        };
+        match self.tempos.binary_search_by_key(&at, |el| el.at) {
+            Ok(idx) => self.tempos[idx] = change,
+            Err(idx) => self.tempos.insert(idx, change),
+        }
+    }
+
+    /// Speeds up or slows down steadily from the tempo at `from` to `bpm` at `to`, replacing the changes between.
+    pub fn ramp_tempo(&mut self, from: Ticks, to: Ticks, bpm: f64) {
+        if to <= from {
+            self.set_tempo(from, bpm);
+            return;
+        }
+        let start = self.tempo_at(from);
+        self.tempos.retain(|el| el.at < from || el.at > to);
+        self.set_tempo(from, start);
+        self.set_tempo(to, bpm);
+        let idx = self.tempo_index(from);
+        self.tempos[idx].ramp = true;
+    }
+
+    /// Tempo at `at` in quarter notes per minute.
+    pub fn tempo_at(&self, at: Ticks) -> f64 {
+        let idx = self.tempo_index(at);
+        let change = self.tempos[idx];
+        self.slope(idx).map_or(change.bpm, |slope| {
+            change.bpm + slope * (at - change.at) as f64
+        })
+    }
+
+    /// Tempo change per tick of the ramp starting at change `idx`, if it ramps.
+    fn slope(&self, idx: usize) -> Option<f64> {
+        let change = self.tempos[idx];
+        let next = self.tempos.get(idx + 1).filter(|_| change.ramp)?;
+        Some((next.bpm - change.bpm) / (next.at - change.at) as f64)
+    }
+
+    /// Seconds spent in the first `ticks` ticks after change `idx`.
+    fn segment_seconds(&self, idx: usize, ticks: f64) -> f64 {
+        let bpm = self.tempos[idx].bpm;
+        // Per tick the time is 60 / (TICKS_PER_QUARTER * tempo), and a ramp integrates that over a tempo that moves
+        // linearly.
+        let scale = 60. / TICKS_PER_QUARTER as f64;
+        match self.slope(idx) {
+            Some(slope) if slope.abs() > 1e-12 => {
+                scale / slope * ((bpm + slope * ticks) / bpm).ln()
+            }
+            _ => scale * ticks / bpm,
+        }
+    }
+
+    /// Time from the start of the score to `at`.
+    pub fn seconds(&self, at: Ticks) -> f64 {
+        let last = self.tempo_index(at);
+        let mut total = 0.;
+        for idx in 0..last {
+            total +=
+                self.segment_seconds(idx, (self.tempos[idx + 1].at - self.tempos[idx].at) as f64);
+        }
+        total + self.segment_seconds(last, (at - self.tempos[last].at) as f64)
+    }
+
+    /// Frame `at` falls on when rendered at `sample_rate`.
+    pub fn frame(&self, at: Ticks, sample_rate: u32) -> u32 {
+        (self.seconds(at) * sample_rate as f64).round() as u32
+    }
+
+    /// The tick sounding `seconds` into the score, rounded to the nearest one.
+    pub fn ticks_at(&self, seconds: f64) -> Ticks {
+        let scale = 60. / TICKS_PER_QUARTER as f64;
+        let mut left = seconds.max(0.);
+        for (idx, change) in self.tempos.iter().enumerate() {
+            if let Some(next) = self.tempos.get(idx + 1) {
+                let length = self.segment_seconds(idx, (next.at - change.at) as f64);
+                if left >= length {
+                    left -= length;
+                    continue;
+                }
+            }
+            let ticks = match self.slope(idx) {
+                Some(slope) if slope.abs() > 1e-12 => {
+                    change.bpm * ((left * slope / scale).exp() - 1.) / slope
+                }
+                _ => left * change.bpm / scale,
+            };
+            return change.at + ticks.round() as Ticks;
+        }
+        unreachable!("a tempo map always holds a change at tick 0")
+    }
+
+    /// Switches to `signature` from the start of `bar` on.
+    pub fn set_time_signature(&mut self, bar: u32, signature: TimeSignature) {
+        let bar = bar.max(1);
+        match self.signatures.binary_search_by_key(&bar, |el| el.0) {
+            Ok(idx) => self.signatures[idx].1 = signature,
+            Err(idx) => self.signatures.insert(idx, (bar, signature)),
+        }
+    }
+
+    pub fn time_signature(&self, bar: u32) -> TimeSignature {
+        let idx = self.signatures.partition_point(|el| el.0 <= bar);
+        self.signatures[idx.saturating_sub(1)].1
+    }
+
+    /// Tick each time signature starts on, along with its bar.
+    fn signature_starts(&self) -> impl Iterator<Item = (Ticks, u32, TimeSignature)> + '_ {
+        let mut tick = 0;
+        let mut bar = 1;
+        let mut previous = TimeSignature::default();
+        self.signatures.iter().map(move |(start, signature)| {
+            tick += (start - bar) * previous.bar_ticks();
+            bar = *start;
+            previous = *signature;
+            (tick, bar, *signature)
+        })
+    }
+
+    /// Tick `position` falls on.
+    pub fn ticks(&self, position: Position) -> Ticks {
+        let (tick, bar, signature) = self
+            .signature_starts()
+            .take_while(|(_, bar, _)| *bar <= position.bar.max(1))
+            .last()
+            .unwrap_or((0, 1, TimeSignature::default()));
+        tick + (position.bar.max(1) - bar) * signature.bar_ticks()
+            + (position.beat.max(1) - 1) * signature.beat_ticks()
+            + position.tick
+    }
+
+    /// Bar, beat and tick of `at`.
+    pub fn position(&self, at: Ticks) -> Position {
+        let (tick, bar, signature) = self
+            .signature_starts()
+            .take_while(|(tick, _, _)| *tick <= at)
+            .last()
+            .unwrap_or((0, 1, TimeSignature::default()));
+        let into = at - tick;
+        let in_bar = into % signature.bar_ticks();
+        Position {
+            bar: bar + into / signature.bar_ticks(),
+            beat: 1 + in_bar / signature.beat_ticks(),
+            tick: in_bar % signature.beat_ticks(),
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashMap, error::Error, fmt, path::Path, time::Duration};
+
+use crate::{
+    DEFAULT_BASE,
+    lattice::Region,
+    note::Harmonym,
+    score::{NoteDuration, Score},
+    wav,
+};
+
+/// Settings for turning a recording into a [`Score`].
+#[derive(Debug, Clone)]
+pub struct TranscribeOptions {
+    /// Frequency the detected harmonyms are measured against.
+    pub base: f32,
+    /// Use spectral peak picking to find several pitches per window instead of autocorrelation.
+    pub polyphonic: bool,
+    /// Analysis window length in samples, at least 2. Must be a power of two for polyphonic detection.
+    pub window: usize,
+    /// Distance between the starts of two windows in samples, at least 1.
+    pub hop: usize,
+    /// Windows quieter than this RMS level are treated as silence.
+    pub silence: f32,
+    /// Spectral peaks weaker than this fraction of the strongest one are ignored.
+    pub threshold: f32,
+    /// Maximum number of simultaneous pitches in polyphonic mode.
+    pub max_voices: usize,
+    /// Drop spectral peaks that sit on a harmonic of a lower accepted peak.
+    pub suppress_harmonics: bool,
+    /// Pitches held shorter than this are discarded.
+    pub min_duration: Duration,
+    /// Largest distance in cents between a detected pitch and the harmonym it is spelled as.
+    pub max_deviation: f32,
+    /// Harmonyms the detected pitches may be spelled as.
+    pub region: Region,
+}
+
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        Self {
+            base: DEFAULT_BASE,
+            polyphonic: false,
+            window: 4096,
+            hop: 1024,
+            silence: 0.01,
+            threshold: 0.1,
+            max_voices: 6,
+            suppress_harmonics: true,
+            min_duration: Duration::from_millis(100),
+            max_deviation: 30.,
+            region: Region {
+                max_tenney_height: Some(12.),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TranscribeError {
+    Wav(hound::Error),
+    Empty,
+    /// The recording is shorter than a single analysis window.
+    TooShort {
+        samples: usize,
+        window: usize,
+    },
+    InvalidOptions(&'static str),
+}
+
+impl fmt::Display for TranscribeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wav(e) => write!(f, "failed to read wav file: {}", e),
+            Self::Empty => write!(f, "recording contains no samples"),
+            Self::TooShort { samples, window } => write!(
+                f,
+                "recording of {} samples is shorter than the analysis window of {}",
+                samples, window
+            ),
+            Self::InvalidOptions(e) => write!(f, "invalid transcription options: {}", e),
+        }
+    }
+}
+
+impl Error for TranscribeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Wav(e) => Some(e),
+            Self::Empty | Self::TooShort { .. } | Self::InvalidOptions(_) => None,
+        }
+    }
+}
+
+impl From<hound::Error> for TranscribeError {
+    fn from(value: hound::Error) -> Self {
+        Self::Wav(value)
+    }
+}
+
+/// Reads a WAV file and transcribes its sustained pitches. Multichannel files are mixed down first.
+pub fn transcribe_file(
+    path: impl AsRef<Path>,
+    options: &TranscribeOptions,
+) -> Result<Score, TranscribeError> {
+    let (samples, sample_rate) = wav::read_mono(path)?;
+    transcribe(&samples, sample_rate, options)
+}
+
+/// Transcribes mono `samples` recorded at `sample_rate` into a score relative to `options.base`.
+pub fn transcribe(
+    samples: &[f32],
+    sample_rate: u32,
+    options: &TranscribeOptions,
+) -> Result<Score, TranscribeError> {
+    options.check()?;
+    if sample_rate == 0 {
+        return Err(TranscribeError::InvalidOptions("sample rate is 0"));
+    }
+    if samples.is_empty() {
+        return Err(TranscribeError::Empty);
+    }
+    if samples.len() < options.window {
+        return Err(TranscribeError::TooShort {
+            samples: samples.len(),
+            window: options.window,
+        });
+    }
+    let mut score = Score::new();
+    score.set_base(options.base);
+    let hop = options.hop;
+    let seconds = |frame: usize| frame as f64 / sample_rate as f64;
+
+    let mut active: HashMap<Harmonym, usize> = HashMap::new();
+    let mut finished: Vec<(usize, usize, Harmonym)> = Vec::new();
+    let mut position = 0;
+    while position + options.window <= samples.len() {
+        let window = &samples[position..position + options.window];
+        let detected = if rms(window) < options.silence {
+            Vec::new()
+        } else if options.polyphonic {
+            detect_chord(window, sample_rate, options)
+        } else {
+            detect_pitch(window, sample_rate)
+                .and_then(|el| spell(el, options))
+                .into_iter()
+                .collect()
+        };
+        active.retain(|harmonym, start| {
+            let held = detected.contains(harmonym);
+            if !held {
+                finished.push((*start, position, *harmonym));
+            }
+            held
+        });
+        for harmonym in detected {
+            active.entry(harmonym).or_insert(position);
+        }
+        position += hop;
+    }
+    finished.extend(
+        active
+            .into_iter()
+            .map(|(harmonym, start)| (start, position, harmonym)),
+    );
+    finished.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+
+    for (start, end, harmonym) in finished {
+        let dur = Duration::from_secs_f64(seconds(end) - seconds(start));
+        if dur < options.min_duration {
+            continue;
+        }
+        let start = score.tempo().ticks_at(seconds(start));
+        let end = score.tempo().ticks_at(seconds(end));
+        score.push((
+            NoteDuration {
+                start,
+                length: end - start,
+            },
+            harmonym,
+        ));
+    }
+    Ok(score)
+}
+
+impl TranscribeOptions {
+    fn check(&self) -> Result<(), TranscribeError> {
+        let error = if self.window < 2 {
+            "window must be at least 2 samples"
+        } else if self.polyphonic && !self.window.is_power_of_two() {
+            "polyphonic detection needs a window that is a power of two"
+        } else if self.hop == 0 {
+            "hop must be at least 1 sample"
+        } else if !(self.base > 0. && self.base.is_finite()) {
+            "base frequency must be positive"
+        } else {
+            return Ok(());
+        };
+        Err(TranscribeError::InvalidOptions(error))
+    }
+}
+
+fn spell(frequency: f32, options: &TranscribeOptions) -> Option<Harmonym> {
+    options
+        .region
+        .lookup(frequency, options.base, options.max_deviation)
+        .first()
+        .map(|el| el.harmonym)
+}
+
+fn rms(window: &[f32]) -> f32 {
+    (window.iter().map(|el| el * el).sum::<f32>() / window.len() as f32).sqrt()
+}
+
+/// Lowest and highest fundamental considered, in Hz.
+const PITCH_RANGE: (f32, f32) = (40., 4000.);
+
+/// Estimates the fundamental of a monophonic window with normalised autocorrelation.
+fn detect_pitch(window: &[f32], sample_rate: u32) -> Option<f32> {
+    let min_lag = (sample_rate as f32 / PITCH_RANGE.1) as usize;
+    let max_lag = ((sample_rate as f32 / PITCH_RANGE.0) as usize).min(window.len() / 2);
+    if min_lag < 1 || min_lag >= max_lag {
+        return None;
+    }
+    let correlation = |lag: usize| {
+        let (mut sum, mut energy_a, mut energy_b) = (0f32, 0f32, 0f32);
+        for i in 0..window.len() - lag {
+            sum += window[i] * window[i + lag];
+            energy_a += window[i] * window[i];
+            energy_b += window[i + lag] * window[i + lag];
+        }
+        let norm = (energy_a * energy_b).sqrt();
+        if norm > 0. { sum / norm } else { 0. }
+    };
+    let curve: Vec<f32> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let best = curve[1..curve.len() - 1]
+        .iter()
+        .copied()
+        .fold(f32::MIN, f32::max);
+    if best < 0.5 {
+        return None;
+    }
+    // The first peak close to the best one avoids picking a subharmonic.
+    let idx = (1..curve.len() - 1).find(|&i| {
+        curve[i] >= 0.9 * best && curve[i] >= curve[i - 1] && curve[i] >= curve[i + 1]
+    })?;
+    let lag =
+        (min_lag - 1 + idx) as f32 + parabolic_offset(curve[idx - 1], curve[idx], curve[idx + 1]);
+    Some(sample_rate as f32 / lag)
+}
+
+/// Picks the strongest spectral peaks of a window and spells each as a harmonym.
+fn detect_chord(window: &[f32], sample_rate: u32, options: &TranscribeOptions) -> Vec<Harmonym> {
+    let size = window.len();
+    let mut spectrum: Vec<(f32, f32)> = window
+        .iter()
+        .enumerate()
+        .map(|(i, el)| {
+            let hann = 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / size as f32).cos();
+            (el * hann, 0.)
+        })
+        .collect();
+    fft(&mut spectrum);
+    let magnitude: Vec<f32> = spectrum[..size / 2]
+        .iter()
+        .map(|(re, im)| (re * re + im * im).sqrt())
+        .collect();
+    let bin_width = sample_rate as f32 / size as f32;
+    let loudest = magnitude.iter().copied().fold(0., f32::max);
+
+    let mut peaks: Vec<(f32, f32)> = (1..magnitude.len() - 1)
+        .filter(|&i| {
+            let frequency = i as f32 * bin_width;
+            magnitude[i] >= options.threshold * loudest
+                && magnitude[i] > magnitude[i - 1]
+                && magnitude[i] >= magnitude[i + 1]
+                && (PITCH_RANGE.0..=PITCH_RANGE.1).contains(&frequency)
+        })
+        .map(|i| {
+            let offset = parabolic_offset(magnitude[i - 1], magnitude[i], magnitude[i + 1]);
+            ((i as f32 + offset) * bin_width, magnitude[i])
+        })
+        .collect();
+    peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut accepted: Vec<f32> = Vec::new();
+    for (frequency, _) in peaks {
+        if accepted.len() >= options.max_voices {
+            break;
+        }
+        let is_harmonic = accepted.iter().any(|&low| {
+            let multiple = frequency / low;
+            multiple > 1.5 && (multiple - multiple.round()).abs() < 0.015 * multiple
+        });
+        if options.suppress_harmonics && is_harmonic {
+            continue;
+        }
+        accepted.push(frequency);
+    }
+
+    let mut ret: Vec<Harmonym> = accepted
+        .into_iter()
+        .filter_map(|el| spell(el, options))
+        .collect();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+/// Offset of the vertex of the parabola through three equally spaced points, relative to the middle one.
+fn parabolic_offset(left: f32, center: f32, right: f32) -> f32 {
+    let denominator = left - 2. * center + right;
+    if denominator.abs() < f32::EPSILON {
+        0.
+    } else {
+        0.5 * (left - right) / denominator
+    }
+}
+
+/// In-place iterative radix-2 FFT over `(re, im)` pairs. The length must be a power of two.
+fn fft(data: &mut [(f32, f32)]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2. * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = data[start + k + len / 2];
+                let twiddled = (re * cos - im * sin, re * sin + im * cos);
+                let even = data[start + k];
+                data[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                data[start + k + len / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        len <<= 1;
+    }
+}
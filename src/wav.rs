@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use rodio::Source;
+
+/// Reads a WAV file as samples between -1 and 1, mixing multichannel files down to mono.
+pub fn read_mono(path: impl AsRef<Path>) -> Result<(Vec<f32>, u32), hound::Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|el| el.map(|el| el as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let samples = interleaved
+        .chunks(spec.channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok((samples, spec.sample_rate))
+}
+
+/// Writes `source` to a 32-bit float WAV file as it plays, so long renders never sit in memory at once.
+pub fn write(path: impl AsRef<Path>, source: impl Source<Item = f32>) -> Result<(), hound::Error> {
+    let spec = WavSpec {
+        channels: source.channels(),
+        sample_rate: source.sample_rate(),
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    for sample in source {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()
+}
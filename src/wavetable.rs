@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+
+use crate::playable::Waveform;
+
+/// Samples per period of the additive wavetables.
+pub const TABLE_SIZE: usize = 2048;
+/// Harmonics a table can hold without aliasing within the table itself.
+const MAX_HARMONICS: usize = TABLE_SIZE / 2;
+/// Bytes of tables the cache keeps unless set otherwise, enough for a dozen ladders at 48 kHz.
+pub const DEFAULT_CAPACITY: usize = 4 << 20;
+/// Top frequency of the lowest band. Anything lower gets as many harmonics as a table holds anyway.
+const LOWEST_BAND: f32 = 20.;
+/// Bands per octave. A voice at the bottom of a band sounds at most this much duller than its own table would.
+const BANDS_PER_OCTAVE: f32 = 4.;
+
+/// Every additive table of one waveform at one sample rate, one band per quarter octave.
+///
+/// Which harmonyms or base frequencies are played only decides which band a voice reads, so the cache holds a ladder
+/// per waveform and sample rate, and a voice can follow any glide or vibrato by moving to another band without
+/// building a table or taking a lock.
+#[derive(Debug)]
+pub struct Ladder {
+    /// Tables from the lowest band up. Bands that hold the same harmonics share a table.
+    tables: Vec<Arc<[f32]>>,
+}
+
+impl Ladder {
+    fn build(waveform: Waveform, sample_rate: u32) -> Self {
+        let nyquist = sample_rate as f32 / 2.;
+        let bands = (BANDS_PER_OCTAVE * (nyquist / LOWEST_BAND).log2())
+            .ceil()
+            .max(0.) as usize
+            + 1;
+        let harmonics: Vec<usize> = (0..bands)
+            .map(|band| match waveform {
+                Waveform::Sine => 1,
+                _ => ((nyquist / band_top(band)).ceil() as usize)
+                    .saturating_sub(1)
+                    .clamp(1, MAX_HARMONICS),
+            })
+            .collect();
+        // Higher bands hold fewer harmonics, so building from the top down only ever adds harmonics to the last
+        // table. Every harmonic lands on whole table positions, which makes one period of a sine enough to read all
+        // of them from.
+        let sine: Vec<f32> = (0..TABLE_SIZE)
+            .map(|i| (2. * PI * i as f32 / TABLE_SIZE as f32).sin())
+            .collect();
+        let mut sum = vec![0.; TABLE_SIZE];
+        let mut built = 0;
+        let mut tables: Vec<Arc<[f32]>> = Vec::with_capacity(bands);
+        for count in harmonics.into_iter().rev() {
+            if let Some(table) = tables.last().filter(|_| count == built) {
+                tables.push(table.clone());
+                continue;
+            }
+            for n in built + 1..=count {
+                let amplitude = amplitude(waveform, n);
+                if amplitude != 0. {
+                    for (i, el) in sum.iter_mut().enumerate() {
+                        *el += amplitude * sine[n * i % TABLE_SIZE];
+                    }
+                }
+            }
+            built = count;
+            tables.push(sum.as_slice().into());
+        }
+        tables.reverse();
+        Self { tables }
+    }
+
+    /// Band for a voice at `frequency` Hz, the lowest one whose table holds no harmonics above Nyquist for it.
+    pub fn band(&self, frequency: f32) -> usize {
+        let band = (BANDS_PER_OCTAVE * (frequency / LOWEST_BAND).log2()).ceil();
+        // `as` saturates, so frequencies below the lowest band, zero and NaN all land on band 0.
+        (band as usize).min(self.tables.len() - 1)
+    }
+
+    /// One period of the waveform for `band`.
+    pub fn table(&self, band: usize) -> &[f32] {
+        &self.tables[band]
+    }
+
+    /// Memory held by the distinct tables of the ladder.
+    pub fn bytes(&self) -> usize {
+        self.distinct() * TABLE_SIZE * size_of::<f32>()
+    }
+
+    /// Distinct tables in the ladder.
+    fn distinct(&self) -> usize {
+        1 + self
+            .tables
+            .windows(2)
+            .filter(|el| !Arc::ptr_eq(&el[0], &el[1]))
+            .count()
+    }
+}
+
+/// Highest frequency `band` is built for.
+fn band_top(band: usize) -> f32 {
+    LOWEST_BAND * (band as f32 / BANDS_PER_OCTAVE).exp2()
+}
+
+/// Amplitude of harmonic `n` in the Fourier series of `waveform`.
+fn amplitude(waveform: Waveform, n: usize) -> f32 {
+    let n_f = n as f32;
+    match waveform {
+        Waveform::Sine => (n == 1) as u8 as f32,
+        Waveform::Square if n % 2 == 1 => 4. / (PI * n_f),
+        Waveform::Saw => {
+            let sign = if n % 2 == 1 { 1. } else { -1. };
+            sign * 2. / (PI * n_f)
+        }
+        Waveform::Triangle if n % 2 == 1 => {
+            let sign = if n % 4 == 1 { 1. } else { -1. };
+            sign * 8. / (PI * PI * n_f * n_f)
+        }
+        _ => 0.,
+    }
+}
+
+/// Counters of the process-wide ladder cache, see [`stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Ladders dropped to stay within the capacity.
+    pub evictions: u64,
+    /// Ladders held now.
+    pub ladders: usize,
+    /// Distinct tables in the ladders held now.
+    pub tables: usize,
+    /// Most bytes of tables held at once, see [`set_capacity`].
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Share of lookups served from the cache, from 0 to 1.
+    pub fn hit_rate(&self) -> f32 {
+        self.hits as f32 / (self.hits + self.misses).max(1) as f32
+    }
+
+    /// Memory held by the cached tables.
+    pub fn bytes(&self) -> usize {
+        self.tables * TABLE_SIZE * size_of::<f32>()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    waveform: Waveform,
+    sample_rate: u32,
+}
+
+/// Ladders keyed by waveform and sample rate, bounded by the bytes of their tables.
+///
+/// Unlike a key per harmonym and base frequency, this holds each table once however many pitches read it: a pitch
+/// only picks a band of the ladder, and every band is built along with the ladder. Least recently used ladders are
+/// evicted first. Voices hold their ladder through an [`Arc`], so eviction never pulls a table out from under a
+/// sounding voice.
+struct Cache {
+    ladders: HashMap<Key, (Arc<Ladder>, u64)>,
+    /// Counts lookups, stamping each ladder with the last one that used it.
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn bytes(&self) -> usize {
+        self.ladders.values().map(|(el, _)| el.bytes()).sum()
+    }
+
+    fn evict(&mut self) {
+        while self.bytes() > self.stats.capacity {
+            let Some(oldest) = self
+                .ladders
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            self.ladders.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.stats.ladders = self.ladders.len();
+        self.stats.tables = self.ladders.values().map(|(el, _)| el.distinct()).sum();
+    }
+}
+
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        Mutex::new(Cache {
+            ladders: HashMap::new(),
+            clock: 0,
+            stats: CacheStats {
+                capacity: DEFAULT_CAPACITY,
+                ..Default::default()
+            },
+        })
+    })
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Cache> {
+    // The cache holds no invariants a panic could break halfway, so a poisoned lock is still usable.
+    cache().lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The band-limited tables of `waveform` at `sample_rate`, shared with every other voice that plays it.
+///
+/// Building a ladder takes a few milliseconds, so call this off the audio thread before the first voice needs it,
+/// e.g. through [`Instrument::prefetch`](crate::instrument::Instrument::prefetch).
+pub fn ladder(waveform: Waveform, sample_rate: u32) -> Arc<Ladder> {
+    let key = Key {
+        waveform,
+        sample_rate,
+    };
+    {
+        let mut cache = lock();
+        cache.clock += 1;
+        let clock = cache.clock;
+        if let Some((ladder, used)) = cache.ladders.get_mut(&key) {
+            *used = clock;
+            let ladder = ladder.clone();
+            cache.stats.hits += 1;
+            return ladder;
+        }
+        cache.stats.misses += 1;
+    }
+    // Built without the lock held, so other threads are not kept waiting. Two threads missing on the same key at once
+    // both build it, and the second simply replaces the first.
+    let ladder = Arc::new(Ladder::build(waveform, sample_rate));
+    let mut cache = lock();
+    let clock = cache.clock;
+    cache.ladders.insert(key, (ladder.clone(), clock));
+    cache.evict();
+    ladder
+}
+
+pub fn stats() -> CacheStats {
+    lock().stats
+}
+
+/// Bounds the memory of the cached tables to `bytes`, evicting the least recently used ladders beyond it. A ladder
+/// larger than the whole capacity is still built for the voices that ask for it, but not kept.
+pub fn set_capacity(bytes: usize) {
+    let mut cache = lock();
+    cache.stats.capacity = bytes;
+    cache.evict();
+}
+
+/// Drops every cached ladder and resets the counters.
+pub fn clear() {
+    let mut cache = lock();
+    cache.ladders.clear();
+    cache.stats = CacheStats {
+        capacity: cache.stats.capacity,
+        ..Default::default()
+    };
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use chalaxata_rs::{
+    backend::{AudioBackend, BufferBackend},
+    engine::{DEFAULT_POLYPHONY, Engine, Note},
+    note::Harmonym,
+    playable::SAMPLE_RATE,
+    resample::{Quality, Resample},
+};
+use rodio::buffer::SamplesBuffer;
+
+#[test]
+fn buffer_backend_renders_engine_until_it_finishes() {
+    let mut backend = BufferBackend::new(SAMPLE_RATE);
+    let (engine, handle) = Engine::new(SAMPLE_RATE, DEFAULT_POLYPHONY);
+    backend.play(Box::new(engine)).unwrap();
+
+    backend.advance(Duration::from_millis(100)).unwrap();
+    assert_eq!(backend.samples().len(), SAMPLE_RATE as usize / 10 * 2);
+    assert!(backend.samples().iter().all(|el| *el == 0.));
+
+    handle
+        .note_on(Note {
+            hold: Some(Duration::from_millis(200)),
+            ..Harmonym::default().into()
+        })
+        .unwrap();
+    handle.finish().unwrap();
+    backend.advance(Duration::from_millis(100)).unwrap();
+    let sounding = &backend.samples()[SAMPLE_RATE as usize / 10 * 2..];
+    assert!(sounding.iter().any(|el| el.abs() > 0.1));
+    assert!(sounding.iter().all(|el| el.abs() <= 1.));
+
+    // The rest of the hold plus the default 300 ms release, then the engine ends by itself.
+    backend.finish().unwrap();
+    let seconds = backend.samples().len() as f32 / 2. / SAMPLE_RATE as f32;
+    assert!((0.59..0.62).contains(&seconds), "rendered {seconds} s");
+}
+
+#[test]
+#[should_panic(expected = "sample rate must not be 0")]
+fn engine_refuses_a_zero_sample_rate() {
+    Engine::new(0, DEFAULT_POLYPHONY);
+}
+
+#[test]
+#[should_panic(expected = "sample rate must not be 0")]
+fn resampling_to_a_zero_rate_is_refused() {
+    let source = SamplesBuffer::new(2, SAMPLE_RATE, vec![0.; 100]);
+    Resample::new(source, 0, Quality::default());
+}
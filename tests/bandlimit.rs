@@ -0,0 +1,74 @@
+use chalaxata_rs::{
+    chord::FullChord,
+    instrument::Instrument,
+    note::Harmonym,
+    playable::{Bandlimit, PlayableChord, Waveform},
+};
+
+const SAMPLE_RATE: usize = 48000;
+/// Prime, so aliases folded back from above Nyquist never land on a harmonic.
+const FUNDAMENTAL: usize = 2903;
+
+/// Fraction of the signal energy that lies outside the harmonics of `FUNDAMENTAL`.
+fn inharmonic_energy(waveform: Waveform, bandlimit: Bandlimit) -> f64 {
+    let mut chord: PlayableChord = FullChord {
+        tones: vec![Harmonym::default()],
+        base: FUNDAMENTAL as f32,
+    }
+    .into();
+    chord.set_instrument(Instrument::Wave {
+        waveform,
+        bandlimit,
+    });
+    chord.prerender();
+    // Skip the attack and decay, then analyse exactly one second of the left channel so every integer frequency falls
+    // on a bin. The output is interleaved stereo.
+    let samples: Vec<f64> = chord
+        .skip(SAMPLE_RATE)
+        .step_by(2)
+        .take(SAMPLE_RATE)
+        .map(f64::from)
+        .collect();
+
+    let total: f64 = samples.iter().map(|el| el * el).sum::<f64>() / SAMPLE_RATE as f64;
+    let harmonic: f64 = (1..)
+        .map(|n| n * FUNDAMENTAL)
+        .take_while(|el| *el < SAMPLE_RATE / 2)
+        .map(|frequency| {
+            let (re, im) = samples
+                .iter()
+                .enumerate()
+                .fold((0., 0.), |(re, im), (t, el)| {
+                    let angle = 2. * std::f64::consts::PI * (frequency * t % SAMPLE_RATE) as f64
+                        / SAMPLE_RATE as f64;
+                    (re + el * angle.cos(), im - el * angle.sin())
+                });
+            2. * (re * re + im * im) / (SAMPLE_RATE as f64).powi(2)
+        })
+        .sum();
+    1. - harmonic / total
+}
+
+#[test]
+fn band_limited_waveforms_do_not_alias() {
+    for waveform in [Waveform::Square, Waveform::Saw, Waveform::Triangle] {
+        let naive = inharmonic_energy(waveform, Bandlimit::Naive);
+        let additive = inharmonic_energy(waveform, Bandlimit::Additive);
+        assert!(
+            additive < 1e-3,
+            "{waveform:?}: additive leaves {additive} of the energy at aliased frequencies"
+        );
+        assert!(
+            additive < naive,
+            "{waveform:?}: additive ({additive}) should alias less than naive ({naive})"
+        );
+    }
+    for waveform in [Waveform::Square, Waveform::Saw] {
+        let naive = inharmonic_energy(waveform, Bandlimit::Naive);
+        let poly_blep = inharmonic_energy(waveform, Bandlimit::PolyBlep);
+        assert!(
+            poly_blep < naive / 4.,
+            "{waveform:?}: PolyBLEP ({poly_blep}) should alias far less than naive ({naive})"
+        );
+    }
+}
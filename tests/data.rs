@@ -0,0 +1,261 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use chalaxata_rs::{
+    data::{Autosave, EditorState, FORMAT_VERSION, Project, ProjectError, SessionData, Track},
+    dsl,
+    effects::Effect,
+    glide::{Glide, GlidePath, Portamento},
+    instrument::{Fm, Instrument, Sample},
+    modulation::{Lfo, Modulation},
+    note::parse_harmonym,
+    score::Score,
+};
+
+fn project() -> Project {
+    let mut melody = dsl::parse(
+        "
+        time 3/4
+        tempo 90
+        Ah/4 ChyLy/8. r/16
+        ramp 140 /2
+        { Ah/2~ Ah/4 | Su-/4 Su- }
+        base 440
+        Chy/1
+        ",
+    )
+    .unwrap();
+    melody.set_instrument(Fm::default());
+    melody.set_effects(vec![
+        Effect::LowPass {
+            cutoff: 2000.,
+            resonance: 0.9,
+        },
+        Effect::Delay {
+            time: Duration::from_millis(375),
+            feedback: 0.4,
+            mix: 0.3,
+        },
+    ]);
+    let vibrato = Lfo::new(5.5, 12.);
+    let to = parse_harmonym("Chy").unwrap().1;
+    for note in melody.notes_mut() {
+        note.modulation.vibrato = Some(vibrato);
+        note.glide = Some(Glide {
+            path: GlidePath::Lattice { portamento: 0.5 },
+            ..Glide::new(to, Duration::from_millis(120))
+        });
+        note.pan = -0.25;
+    }
+    let mut bass = Score::new();
+    bass.set_base(130.81);
+    Project {
+        tracks: vec![
+            Track {
+                name: "melody".to_owned(),
+                muted: false,
+                score: melody,
+            },
+            Track {
+                name: "bass".to_owned(),
+                muted: true,
+                score: bass,
+            },
+        ],
+        editor: EditorState {
+            track: 1,
+            cursor: 1920,
+            modulation: Modulation {
+                vibrato: None,
+                tremolo: Some(Lfo::new(3., 0.5)),
+            },
+            portamento: Some(Portamento {
+                duration: Duration::from_micros(80_500),
+                path: GlidePath::Direct,
+            }),
+            effects: vec![Effect::Reverb {
+                room_size: 0.8,
+                damping: 0.3,
+                mix: 0.25,
+            }],
+            ..EditorState::default()
+        },
+    }
+}
+
+#[test]
+fn projects_survive_a_round_trip() {
+    let project = project();
+    let json = project.to_json().unwrap();
+    assert_eq!(Project::from_json(&json).unwrap(), project);
+
+    let path = std::env::temp_dir().join(format!("chalaxata-project-{}.json", std::process::id()));
+    let mut session = SessionData::new();
+    assert!(matches!(session.save(), Err(ProjectError::NoPath)));
+    *session.project_mut() = project;
+    assert!(session.is_modified());
+    session.save_as(&path).unwrap();
+    assert!(!session.is_modified());
+    let opened = SessionData::open(&path).unwrap();
+    assert_eq!(opened.project(), session.project());
+    assert_eq!(opened.path(), Some(path.as_path()));
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn older_formats_are_upgraded_and_newer_ones_refused() {
+    let project = project();
+    let mut value: serde_json::Value = serde_json::from_str(&project.to_json().unwrap()).unwrap();
+    // Version 0 had no editor section.
+    value["version"] = 0.into();
+    value.as_object_mut().unwrap().remove("editor");
+    let upgraded = Project::from_json(&value.to_string()).unwrap();
+    assert_eq!(upgraded.tracks, project.tracks);
+    assert_eq!(upgraded.editor, EditorState::default());
+
+    value["version"] = (FORMAT_VERSION + 1).into();
+    assert!(matches!(
+        Project::from_json(&value.to_string()),
+        Err(ProjectError::UnsupportedVersion(Some(version))) if version == FORMAT_VERSION as u64 + 1
+    ));
+}
+
+#[test]
+fn damaged_and_unknown_files_are_refused() {
+    let json = project().to_json().unwrap();
+    assert!(matches!(
+        Project::from_json(&json[..json.len() / 2]),
+        Err(ProjectError::Corrupt(_))
+    ));
+    let missing = json.replacen("\"tracks\"", "\"trucks\"", 1);
+    assert!(matches!(
+        Project::from_json(&missing),
+        Err(ProjectError::Corrupt(_))
+    ));
+    let newer = json.replacen("\"version\": 1", "\"version\": 99", 1);
+    assert!(matches!(
+        Project::from_json(&newer),
+        Err(ProjectError::UnsupportedVersion(Some(99)))
+    ));
+    assert!(matches!(
+        Project::from_json("{}"),
+        Err(ProjectError::UnsupportedVersion(None))
+    ));
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["tracks"][0]["tempo"]["signatures"][0]["beats"] = 5_000_000.into();
+    assert!(matches!(
+        Project::from_json(&value.to_string()),
+        Err(ProjectError::Invalid(_))
+    ));
+    let late_start = json.replacen("\"at\": 0", "\"at\": 5", 1);
+    assert!(matches!(
+        Project::from_json(&late_start),
+        Err(ProjectError::Invalid(_))
+    ));
+
+    let damaged = |edit: fn(&mut serde_json::Value)| {
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        edit(&mut value["tracks"][0]["notes"][0]);
+        Project::from_json(&value.to_string())
+    };
+    assert!(damaged(|_| ()).is_ok());
+    for edit in [
+        (|note| note["start"] = u32::MAX.into()) as fn(&mut serde_json::Value),
+        |note| note["pan"] = 3.into(),
+        |note| note["envelope"]["sustain"] = (-0.5).into(),
+        |note| note["vibrato"]["rate"] = (-1).into(),
+        |note| note["glide"]["path"]["portamento"] = 2.into(),
+        |note| note["base"] = 0.into(),
+        |note| note["harmonym"][4] = 9.into(),
+    ] {
+        assert!(matches!(damaged(edit), Err(ProjectError::Invalid(_))));
+    }
+    assert!(matches!(
+        damaged(|note| note["envelope"]["attack"] = (-10).into()),
+        Err(ProjectError::Corrupt(_))
+    ));
+
+    // Numbers too large for an f32 are read as infinite.
+    let damaged = |edit: fn(&mut serde_json::Value)| {
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        edit(&mut value["tracks"][0]);
+        Project::from_json(&value.to_string())
+    };
+    assert!(damaged(|_| ()).is_ok());
+    for edit in [
+        (|track| track["effects"][0]["cutoff"] = 1e39.into()) as fn(&mut serde_json::Value),
+        |track| track["effects"][0]["resonance"] = (-1e39).into(),
+        |track| track["effects"][1]["time"] = 1e9.into(),
+        |track| track["effects"][1]["feedback"] = 1e39.into(),
+        |track| track["instrument"]["ratio"] = 1e39.into(),
+        |track| track["instrument"]["index"] = 1e39.into(),
+        |track| {
+            track["instrument"] =
+                serde_json::json!({"type": "pluck", "decay": 1e39, "brightness": 0.5})
+        },
+        |track| {
+            track["instrument"] =
+                serde_json::json!({"type": "additive", "partials": [[1, 1], [2, 1e39]]})
+        },
+    ] {
+        assert!(matches!(damaged(edit), Err(ProjectError::Invalid(_))));
+    }
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["editor"]["effects"][0]["mix"] = 1e39.into();
+    assert!(matches!(
+        Project::from_json(&value.to_string()),
+        Err(ProjectError::Invalid(_))
+    ));
+
+    let mut unsaved = project();
+    unsaved.editor.instrument =
+        Instrument::from(Sample::new(vec![0., 1., 0., -1.], 44100, 440.).unwrap());
+    assert!(matches!(
+        unsaved.to_json(),
+        Err(ProjectError::UnsavedSample)
+    ));
+}
+
+#[test]
+fn unsaved_changes_are_recovered_until_saved() {
+    let dir = std::env::temp_dir().join(format!("chalaxata-recovery-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("song.json");
+    let mut session = SessionData::new();
+    session.save_as(&path).unwrap();
+    assert!(!session.autosave().unwrap());
+
+    session.project_mut().tracks = project().tracks;
+    assert!(session.autosave().unwrap());
+    assert!(!session.autosave().unwrap());
+    assert_eq!(session.recovery_path(), dir.join("song.json.recovery"));
+    let recovered = SessionData::recover(Some(&path)).unwrap().unwrap();
+    assert_eq!(recovered.project(), session.project());
+    assert_eq!(recovered.path(), Some(path.as_path()));
+    assert!(recovered.is_modified());
+    // The project file itself still holds what was saved.
+    assert!(Project::load(&path).unwrap().tracks.is_empty());
+
+    session.save().unwrap();
+    assert!(SessionData::recover(Some(&path)).unwrap().is_none());
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|el| el.unwrap().file_name())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["song.json"]);
+
+    let shared = Arc::new(Mutex::new(session));
+    let autosave = Autosave::start(shared.clone(), Duration::from_millis(10), |e| {
+        panic!("autosave failed: {e}")
+    });
+    shared.lock().unwrap().project_mut().editor.cursor = 960;
+    thread::sleep(Duration::from_millis(500));
+    drop(autosave);
+    let recovered = SessionData::recover(Some(&path)).unwrap().unwrap();
+    assert_eq!(recovered.project().editor.cursor, 960);
+    std::fs::remove_dir_all(dir).ok();
+}
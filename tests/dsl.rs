@@ -0,0 +1,105 @@
+use chalaxata_rs::{
+    dsl::{self, DslError},
+    note::{Harmonym, parse_harmonym},
+    time::note_length,
+};
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+#[test]
+fn score_text_lays_out_notes_in_musical_time() {
+    let score = dsl::parse(
+        "
+        tempo 90   # slow
+        Ah/4 ChyLy/8. r/16
+        Chy/2~ Chy/8 Ly+
+        { Ah/2 | Su-/4 Su- }
+        ( Chy/8 )*3
+        base 440
+        Ah/1
+        ",
+    )
+    .unwrap();
+    let quarter = note_length(1, 4);
+    let notes: Vec<_> = score
+        .notes()
+        .iter()
+        .map(|el| (el.harmonym, el.duration.start, el.duration.length))
+        .collect();
+    let after_tie = quarter * 2 + quarter * 2 + note_length(1, 8);
+    let after_voices = after_tie + note_length(1, 8) + quarter * 2;
+    assert_eq!(
+        notes,
+        [
+            (harmonym("Ah"), 0, quarter),
+            (harmonym("Chy"), quarter, note_length(3, 16)),
+            (harmonym("Ly"), quarter, note_length(3, 16)),
+            (harmonym("Chy"), quarter * 2, note_length(5, 8)),
+            (harmonym("Ly+"), after_tie, note_length(1, 8)),
+            (harmonym("Ah"), after_tie + note_length(1, 8), quarter * 2),
+            (harmonym("Su-"), after_tie + note_length(1, 8), quarter),
+            (harmonym("Su-"), after_tie + note_length(3, 8), quarter),
+            (harmonym("Chy"), after_voices, note_length(1, 8)),
+            (
+                harmonym("Chy"),
+                after_voices + note_length(1, 8),
+                note_length(1, 8)
+            ),
+            (
+                harmonym("Chy"),
+                after_voices + note_length(2, 8),
+                note_length(1, 8)
+            ),
+            (
+                harmonym("Ah"),
+                after_voices + note_length(3, 8),
+                note_length(1, 1)
+            ),
+        ]
+    );
+    assert_eq!(score.notes()[0].base, None);
+    assert_eq!(score.notes()[11].base, Some(440.));
+    assert!((score.tempo().seconds(quarter) - 60. / 90.).abs() < 1e-9);
+}
+
+#[test]
+fn score_text_errors_point_at_the_problem() {
+    let Err(DslError::Syntax {
+        line,
+        column,
+        found,
+    }) = dsl::parse("Ah/4\n( Chy/8 Chq/8 )*2")
+    else {
+        panic!("expected a syntax error");
+    };
+    assert_eq!((line, column, found.as_str()), (2, 9, "Chq/8"));
+}
+
+#[test]
+fn repeats_that_run_too_long_are_refused() {
+    let Err(DslError::TooLong { line, column }) = dsl::parse("Ah/4\n( Chy/1 )*4000000") else {
+        panic!("expected the score to run too long");
+    };
+    assert_eq!((line, column), (2, 3));
+    assert!(matches!(
+        dsl::parse("((((( base Chy )*100)*100)*100)*100)*100"),
+        Err(DslError::TooLong { .. })
+    ));
+    assert!(dsl::parse("( Chy/1 )*1000").is_ok());
+}
+
+#[test]
+fn time_signatures_with_too_many_beats_are_refused() {
+    let Err(DslError::Syntax {
+        line,
+        column,
+        found,
+    }) = dsl::parse("Ah/4\ntime 5000000/4\nAh/4")
+    else {
+        panic!("expected a syntax error");
+    };
+    assert_eq!((line, column, found.as_str()), (2, 1, "time"));
+    assert!(dsl::parse("time 256/1\nAh/1 Ah/1").is_ok());
+}
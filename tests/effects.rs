@@ -0,0 +1,104 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use chalaxata_rs::effects::{Delay, Effect, LowPass, MAX_DELAY, Reverb, chain};
+use rodio::{Source, buffer::SamplesBuffer};
+
+/// One second of a stereo sine at `frequency` Hz.
+fn sine(frequency: f32, sample_rate: u32) -> SamplesBuffer<f32> {
+    let samples = (0..sample_rate)
+        .flat_map(|frame| {
+            let el = (TAU * frequency * frame as f32 / sample_rate as f32).sin();
+            [el, el]
+        })
+        .collect::<Vec<_>>();
+    SamplesBuffer::new(2, sample_rate, samples)
+}
+
+fn impulse(channels: u16, sample_rate: u32, frames: usize) -> SamplesBuffer<f32> {
+    let mut samples = vec![0.; frames * channels as usize];
+    samples[..channels as usize].fill(1.);
+    SamplesBuffer::new(channels, sample_rate, samples)
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0., |peak, el| peak.max(el.abs()))
+}
+
+#[test]
+fn low_pass_keeps_lows_and_cuts_highs() {
+    let low: Vec<f32> = LowPass::new(sine(100., 48000), 1000., 0.707).collect();
+    assert_eq!(low.len(), 96000);
+    assert!((peak(&low[48000..]) - 1.).abs() < 0.05);
+    let high: Vec<f32> = LowPass::new(sine(10000., 48000), 1000., 0.707).collect();
+    assert!(peak(&high[48000..]) < 0.02);
+    // Both channels are filtered alike.
+    assert!(high.chunks(2).all(|el| el[0] == el[1]));
+}
+
+#[test]
+fn delay_echoes_and_ends_after_its_tail() {
+    let out: Vec<f32> =
+        Delay::new(impulse(1, 1000, 10), Duration::from_millis(100), 0.5, 0.5).collect();
+    assert_eq!(out[0], 0.5);
+    assert_eq!(out[100], 0.5);
+    assert_eq!(out[200], 0.25);
+    assert_eq!(out[150], 0.);
+    // Echoes fade below the silence threshold after 13 repeats, then one silent pass ends the output.
+    assert!(
+        out.len() > 1300 && out.len() < 1600,
+        "{} samples",
+        out.len()
+    );
+
+    let silence = SamplesBuffer::new(2, 1000, vec![0.; 20]);
+    let out: Vec<f32> = Delay::new(silence, Duration::from_millis(50), 0.9, 0.5).collect();
+    assert!(
+        out.len() <= 20 + 2 * 52 && out.len().is_multiple_of(2),
+        "{} samples",
+        out.len()
+    );
+    assert!(out.iter().all(|el| *el == 0.));
+}
+
+#[test]
+fn delay_times_are_capped() {
+    let out: Vec<f32> = Delay::new(impulse(1, 1000, 10), Duration::MAX, 0., 0.5).collect();
+    let echo = (MAX_DELAY.as_secs() * 1000) as usize;
+    assert_eq!(out[echo], 0.5);
+    assert!(out[1..echo].iter().all(|el| *el == 0.));
+}
+
+#[test]
+fn reverb_rings_out_and_stops() {
+    let out: Vec<f32> = Reverb::new(impulse(2, 44100, 100), 0.8, 0.3, 0.5).collect();
+    assert!(out.len().is_multiple_of(2));
+    assert!(
+        out.len() > 44100 / 2 && out.len() < 44100 * 30,
+        "{} samples",
+        out.len()
+    );
+    assert!(peak(&out[200..]) > 1e-3);
+    assert!(peak(&out[out.len() - 2000..]) < 1e-3);
+    // The channels are decorrelated.
+    assert!(out[200..].chunks(2).any(|el| el[0] != el[1]));
+}
+
+#[test]
+fn chains_apply_effects_in_order() {
+    let effects = [
+        Effect::LowPass {
+            cutoff: 1000.,
+            resonance: 0.707,
+        },
+        Effect::Delay {
+            time: Duration::from_millis(100),
+            feedback: 0.,
+            mix: 1.,
+        },
+    ];
+    let out = chain(sine(10000., 48000), &effects);
+    assert_eq!((out.channels(), out.sample_rate()), (2, 48000));
+    let out: Vec<f32> = out.collect();
+    assert!(out.len() > 96000);
+    assert!(peak(&out) < 0.1);
+}
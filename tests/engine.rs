@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use chalaxata_rs::{
+    engine::{Engine, Note},
+    note::{Harmonym, parse_harmonym},
+    playable::Waveform,
+};
+
+const SAMPLE_RATE: u32 = 8000;
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+fn note(name: &str, pan: f32) -> Note {
+    Note {
+        pan,
+        ..harmonym(name).into()
+    }
+}
+
+/// Plays `frames` stereo frames and returns the power of the left and right channel.
+fn power(engine: &mut Engine, frames: usize) -> [f32; 2] {
+    let mut total = [0.; 2];
+    for _ in 0..frames {
+        let left = engine.next().unwrap();
+        let right = engine.next().unwrap();
+        total[0] += left * left;
+        total[1] += right * right;
+    }
+    total.map(|el| el / frames as f32)
+}
+
+#[test]
+fn oldest_held_voice_is_stolen_first() {
+    let (mut engine, handle) = Engine::new(SAMPLE_RATE, 2);
+    handle.set_instrument(Waveform::Sine).unwrap();
+    handle.note_on(note("Ah", -1.)).unwrap();
+    power(&mut engine, 800);
+    handle.note_on(note("Chy", 1.)).unwrap();
+    let [left, right] = power(&mut engine, 800);
+    assert!(left > 0.01 && right > 0.01);
+
+    // The third voice pushes out the hard-left one, which started first.
+    handle.note_on(note("Ly", 0.)).unwrap();
+    power(&mut engine, 400);
+    let [left, right] = power(&mut engine, 800);
+    assert!(right > 2. * left, "left {left}, right {right}");
+}
+
+#[test]
+fn released_voices_are_stolen_before_held_ones() {
+    let (mut engine, handle) = Engine::new(SAMPLE_RATE, 2);
+    handle.set_instrument(Waveform::Sine).unwrap();
+    handle.note_on(note("Ah", -1.)).unwrap();
+    power(&mut engine, 800);
+    handle.note_on(note("Chy", 1.)).unwrap();
+    power(&mut engine, 800);
+    handle.note_off(harmonym("Chy")).unwrap();
+    power(&mut engine, 80);
+
+    // The released hard-right voice makes room even though it is younger.
+    handle.note_on(note("Ly", 0.)).unwrap();
+    power(&mut engine, 400);
+    let [left, right] = power(&mut engine, 800);
+    assert!(left > 2. * right, "left {left}, right {right}");
+}
+
+#[test]
+fn engine_ends_once_every_voice_has_rung_out() {
+    let (engine, handle) = Engine::new(SAMPLE_RATE, 2);
+    for name in ["Ah", "Chy", "Ly", "Su-"] {
+        handle
+            .note_on(Note {
+                hold: Some(Duration::from_millis(100)),
+                ..harmonym(name).into()
+            })
+            .unwrap();
+    }
+    handle.finish().unwrap();
+    // A tenth of a second held, the default release of 300 ms, and room for the stolen voices to fade.
+    let samples = engine.count();
+    assert!(samples.is_multiple_of(2));
+    let seconds = samples as f32 / 2. / SAMPLE_RATE as f32;
+    assert!((0.4..0.45).contains(&seconds), "{seconds} s");
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use chalaxata_rs::envelope::{Adsr, Envelope};
+
+/// An envelope at 1000 Hz, so every millisecond is one frame.
+fn envelope(sustain: f32) -> Envelope {
+    Envelope::new(
+        Adsr {
+            attack: Duration::from_millis(10),
+            decay: Duration::from_millis(20),
+            sustain,
+            release: Duration::from_millis(40),
+        },
+        1000,
+    )
+}
+
+#[test]
+fn stages_follow_each_other() {
+    let mut envelope = envelope(0.5);
+    let attack: Vec<f32> = envelope.by_ref().take(10).collect();
+    assert_eq!(attack[0], 0.);
+    assert!(attack.windows(2).all(|el| el[1] > el[0]));
+    // The decay starts at full level.
+    assert_eq!(envelope.next(), Some(1.));
+    let decay: Vec<f32> = envelope.by_ref().take(19).collect();
+    assert!(decay.windows(2).all(|el| el[1] < el[0]));
+    assert!(decay.iter().all(|el| *el > 0.5));
+    assert!(envelope.by_ref().take(1000).all(|el| el == 0.5));
+    assert!(!envelope.is_released());
+
+    envelope.note_off();
+    assert!(envelope.is_released());
+    let release: Vec<f32> = envelope.by_ref().take(40).collect();
+    assert_eq!(release[0], 0.5);
+    assert!(release.windows(2).all(|el| el[1] < el[0]));
+    assert!(!envelope.is_finished());
+    assert_eq!(envelope.next(), Some(0.));
+    assert!(envelope.is_finished());
+    assert!(envelope.take(10).all(|el| el == 0.));
+}
+
+#[test]
+fn release_starts_from_the_current_level() {
+    let mut envelope = envelope(0.5);
+    let level = envelope.by_ref().take(6).last().unwrap();
+    assert_eq!(level, envelope.level());
+    assert!(level > 0. && level < 1.);
+    envelope.note_off();
+    assert_eq!(envelope.next(), Some(level));
+    // A second note-off does not restart the release.
+    let halfway = envelope.by_ref().take(20).last().unwrap();
+    envelope.note_off();
+    assert!(envelope.next().unwrap() < halfway);
+    assert_eq!(envelope.by_ref().take(40).last(), Some(0.));
+    assert!(envelope.is_finished());
+}
+
+#[test]
+fn sustain_is_kept_between_silence_and_full_level() {
+    for (sustain, held) in [(1.5, 1.), (-0.5, 0.), (f32::NAN, 0.)] {
+        let mut envelope = envelope(sustain);
+        assert_eq!(envelope.by_ref().nth(100), Some(held));
+    }
+}
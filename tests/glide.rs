@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use chalaxata_rs::{
+    glide::{Glide, GlidePath, Gliding},
+    note::{Harmonym, parse_harmonym},
+};
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+fn lattice(to: &str, portamento: f32) -> Glide {
+    Glide {
+        path: GlidePath::Lattice { portamento },
+        ..Glide::new(harmonym(to), Duration::from_millis(30))
+    }
+}
+
+#[test]
+fn lattice_paths_step_through_neighbouring_points() {
+    let direct = Glide::new(harmonym("Scyli"), Duration::from_millis(30));
+    assert_eq!(
+        direct.points(harmonym("Ah")),
+        [harmonym("Ah"), harmonym("Scyli")]
+    );
+    // One step at a time, always along the dimension furthest behind.
+    assert_eq!(
+        lattice("Scyli", 0.5).points(harmonym("Ah")),
+        [
+            harmonym("Ah"),
+            harmonym("Chy"),
+            harmonym("Chyli"),
+            harmonym("Scyli"),
+        ]
+    );
+    assert_eq!(
+        lattice("Fu", 0.5).points(harmonym("Chy")),
+        [harmonym("Chy"), harmonym("Ah"), harmonym("Fu")]
+    );
+    assert_eq!(lattice("Ly", 0.5).points(harmonym("Ly")), [harmonym("Ly")]);
+}
+
+#[test]
+fn glides_hold_then_land_exactly_on_their_target() {
+    let glide = Glide {
+        delay: Duration::from_millis(10),
+        ..Glide::new(harmonym("Chy"), Duration::from_millis(30))
+    };
+    let ratios: Vec<f32> = Gliding::new(&glide, harmonym("Ly"), 1000).collect();
+    assert_eq!(ratios.len(), 40);
+    assert!(ratios[..10].iter().all(|el| *el == harmonym("Ly") * 1.));
+    assert!(ratios[10..].windows(2).all(|el| el[1] > el[0]));
+    assert_eq!(ratios.last(), Some(&(harmonym("Chy") * 1.)));
+}
+
+#[test]
+fn lattice_glides_rest_on_each_point() {
+    let ratios: Vec<f32> = Gliding::new(&lattice("Scy", 0.), harmonym("Ah"), 1000).collect();
+    assert_eq!(ratios.len(), 30);
+    assert!(ratios[..14].iter().all(|el| *el == harmonym("Chy") * 1.));
+    assert!(ratios[15..].iter().all(|el| *el == harmonym("Scy") * 1.));
+}
+
+#[test]
+fn interrupted_glides_continue_from_the_sounding_pitch() {
+    let halfway = (harmonym("Ly").cents() + harmonym("Chy").cents()) / 2.;
+    let glide = Glide::new(harmonym("Ah"), Duration::from_millis(30));
+    let ratios: Vec<f32> = Gliding::new(&glide, harmonym("Chy"), 1000)
+        .starting_at(halfway)
+        .collect();
+    let first = 1200. * ratios[0].log2();
+    assert!(first < halfway && halfway - first < 20., "{first} cents");
+    assert_eq!(ratios.last(), Some(&1.));
+}
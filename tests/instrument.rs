@@ -0,0 +1,68 @@
+use chalaxata_rs::instrument::{AdditiveVoice, Instrument, Pluck, Sample, SampleError, Timbre};
+
+#[test]
+fn additive_voices_stay_within_full_scale() {
+    let timbre = Timbre::harmonic(&[1., -0.5, 0.25]);
+    let voice = AdditiveVoice::new(&timbre, 440., 48000);
+    let peak = voice.take(48000).fold(0f32, |peak, el| peak.max(el.abs()));
+    assert!(peak > 0.5 && peak <= 1., "peak of {peak}");
+}
+
+#[test]
+fn silent_timbres_play_silence() {
+    for timbre in [
+        Timbre::harmonic(&[0., 0.]),
+        Timbre::default(),
+        Timbre::harmonic(&[1., f32::NAN]),
+    ] {
+        let voice = AdditiveVoice::new(&timbre, 440., 48000);
+        assert!(voice.take(1000).all(|el| el == 0.), "{timbre:?}");
+    }
+}
+
+#[test]
+fn samples_need_a_positive_root() {
+    for root in [0., -440., f32::NAN, f32::INFINITY] {
+        assert!(matches!(
+            Sample::new(vec![0., 1., 0., -1.], 44100, root),
+            Err(SampleError::InvalidRoot(_))
+        ));
+    }
+    assert!(matches!(
+        Sample::new(Vec::new(), 44100, 440.),
+        Err(SampleError::Empty)
+    ));
+    assert!(matches!(
+        Sample::new(vec![0., 1., 0., -1.], 0, 440.),
+        Err(SampleError::ZeroSampleRate)
+    ));
+    let mut sample = Sample::new(vec![0., 1., 0., -1.], 44100, 440.).unwrap();
+    assert_eq!(sample.root(), 440.);
+    assert!(matches!(
+        sample.set_loop(3, 2),
+        Err(SampleError::InvalidLoop)
+    ));
+    assert!(sample.set_loop(1, 4).is_ok());
+}
+
+#[test]
+fn samples_pitched_far_up_stay_finite() {
+    let data = (0..48000).map(|el| (el as f32 * 0.05).sin()).collect();
+    let sample = Instrument::from(Sample::new(data, 48000, 1.).unwrap());
+    let voice = sample.generator(1e6, 48000, 0);
+    assert!(voice.take(100).all(f32::is_finite));
+}
+
+#[test]
+fn plucks_differ_by_seed_but_render_the_same_way_every_time() {
+    let pluck = |seed: u64| -> Vec<f32> {
+        Instrument::from(Pluck::default())
+            .generator(440., 48000, seed)
+            .take(4800)
+            .collect()
+    };
+    assert_eq!(pluck(1), pluck(1));
+    assert_ne!(pluck(1), pluck(2));
+    assert_ne!(pluck(1), pluck(1 << 32));
+    assert!(pluck(1).iter().any(|el| el.abs() > 0.5));
+}
@@ -0,0 +1,149 @@
+use chalaxata_rs::{
+    DEFAULT_BASE,
+    lattice::{Order, Region, lookup_frequency},
+    note::{Harmonym, parse_harmonym},
+};
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+#[test]
+fn regions_visit_every_harmonym_in_bounds_once() {
+    assert_eq!(Region::default().iter().count(), 7usize.pow(5));
+    let fifths = Region {
+        bounds: [0..=0, -1..=2, 0..=0, 0..=0, 0..=0],
+        ..Region::default()
+    };
+    let found: Vec<_> = fifths.iter().collect();
+    assert_eq!(
+        found,
+        [
+            harmonym("Fu"),
+            harmonym("Ah"),
+            harmonym("Chy"),
+            harmonym("Scy")
+        ]
+    );
+    // Nothing beyond the named range is visited, however wide the bounds.
+    let wide = Region {
+        bounds: [0..=0, -100..=100, 0..=0, 0..=0, 0..=0],
+        ..Region::default()
+    };
+    assert_eq!(wide.iter().count(), 7);
+    let empty = Region {
+        bounds: [0..=0, 5..=9, 0..=0, 0..=0, 0..=0],
+        ..Region::default()
+    };
+    assert_eq!(empty.iter().count(), 0);
+}
+
+#[test]
+fn limits_filter_the_region() {
+    let simple = Region {
+        max_tenney_height: Some(4.5),
+        ..Region::default()
+    };
+    let mut found = simple.sorted(Order::Pitch);
+    assert!(found.iter().all(|el| el.tenney_height() <= 4.5));
+    assert!(found.contains(&harmonym("Chy")));
+    assert!(found.contains(&harmonym("Ly")));
+    // 9/8 has a Tenney height of log2(72).
+    assert!(!found.contains(&harmonym("Scy")));
+    found.retain(|el| el.cents() >= 0. && el.cents() < 1200.);
+    assert_eq!(
+        found,
+        [
+            harmonym("Ah"),
+            harmonym("Ly"),
+            harmonym("Fu+"),
+            harmonym("Chy"),
+            Harmonym::from_exponents([1, -1, 1, 0, 0]).unwrap(),
+        ]
+    );
+
+    let seven_limit = Region {
+        odd_limit: Some(7),
+        cents: Some(0.0..=750.),
+        ..Region::default()
+    };
+    assert!(seven_limit.iter().all(|el| el.odd_limit() <= 7));
+    assert!(
+        seven_limit
+            .iter()
+            .all(|el| (0.0..=750.).contains(&el.cents()))
+    );
+    assert!(seven_limit.iter().any(|el| el == harmonym("Chy")));
+}
+
+#[test]
+fn orderings_sort_by_pitch_or_complexity() {
+    let region = Region {
+        bounds: [-1..=1, -2..=2, -1..=1, 0..=0, 0..=0],
+        ..Region::default()
+    };
+    let by_pitch = region.sorted(Order::Pitch);
+    assert_eq!(by_pitch.len(), region.iter().count());
+    assert!(by_pitch.windows(2).all(|el| el[0].cents() <= el[1].cents()));
+    assert_eq!(
+        by_pitch.first(),
+        Harmonym::from_exponents([-1, -2, -1, 0, 0]).as_ref()
+    );
+    assert_eq!(
+        by_pitch.last(),
+        Harmonym::from_exponents([1, 2, 1, 0, 0]).as_ref()
+    );
+
+    let by_complexity = region.sorted(Order::Complexity);
+    assert_eq!(by_complexity[0], Harmonym::default());
+    assert!(by_complexity.windows(2).all(|el| {
+        let (a, b) = (el[0].tenney_height(), el[1].tenney_height());
+        a < b || (a == b && el[0].cents() <= el[1].cents())
+    }));
+    // The octaves are the simplest intervals after the unison, lowest first.
+    assert_eq!(by_complexity[1], harmonym("Ah-"));
+    assert_eq!(by_complexity[2], harmonym("Ah+"));
+}
+
+#[test]
+fn near_equivalents_find_commas_with_and_without_names() {
+    // 64/63 is the septimal comma, two fifths and a seventh below two octaves.
+    let septimal = Harmonym::from_exponents([2, -2, 0, -1, 0]).unwrap();
+    let found = Region::default().near_equivalents(Harmonym::default(), 30.);
+    let near = found.iter().find(|el| el.harmonym == septimal).unwrap();
+    assert_eq!(near.comma, Some(septimal));
+    assert!((near.cents - 27.26).abs() < 0.01);
+    assert!(
+        found
+            .windows(2)
+            .all(|el| el[0].cents.abs() <= el[1].cents.abs())
+    );
+
+    // 81/80, the syntonic comma, takes four fifths, one more than has a name.
+    let xcy = harmonym("Xcy");
+    let found = Region::default().near_equivalents(xcy, 25.);
+    let ten_thirds = Harmonym::from_exponents([2, -1, 1, 0, 0]).unwrap();
+    let near = found.iter().find(|el| el.harmonym == ten_thirds).unwrap();
+    assert_eq!(near.comma, None);
+    assert!((near.cents + 21.51).abs() < 0.01, "{}", near.cents);
+}
+
+#[test]
+fn lookup_prefers_simple_close_harmonyms() {
+    let found = Region::default().lookup(660., 440., 20.);
+    assert_eq!(found[0].harmonym, harmonym("Chy"));
+    assert!(found[0].deviation.abs() < 0.01);
+    assert!(found.iter().all(|el| el.deviation.abs() <= 20.));
+    assert!(found.windows(2).all(|el| el[0].score() <= el[1].score()));
+
+    // Five cents sharp of 3/2, a fifth still beats anything more complex that lies closer.
+    let sharp = 660. * (5f32 / 1200.).exp2();
+    let found = Region::default().lookup(sharp, 440., 20.);
+    assert_eq!(found[0].harmonym, harmonym("Chy"));
+    assert!((found[0].deviation - 5.).abs() < 0.01);
+    assert!(found.iter().any(|el| el.deviation.abs() < 5.));
+
+    let found = lookup_frequency(DEFAULT_BASE * 1.25);
+    assert_eq!(found[0].harmonym, harmonym("Ly"));
+    assert!(Region::default().lookup(440., 440., -1.).is_empty());
+}
@@ -0,0 +1,63 @@
+use chalaxata_rs::mix::{Mixer, pan, voice_gain};
+
+#[test]
+fn voices_are_normalised_by_power() {
+    assert_eq!(voice_gain(0), 1.);
+    assert_eq!(voice_gain(1), 1.);
+    assert_eq!(voice_gain(4), 0.5);
+
+    let mut mixer = Mixer::new(48000);
+    mixer.set_voices(4);
+    assert_eq!(mixer.mix([1., -1.], 4), [0.5, -0.5]);
+    // The gain eases towards a new voice count instead of jumping.
+    let eased = mixer.mix([0.2, 0.2], 1)[0];
+    assert!(eased > 0.1 && eased < 0.2, "{eased}");
+    let settled = (0..48000).map(|_| mixer.mix([0.2, 0.2], 1)[0]).last();
+    assert!((settled.unwrap() - 0.2).abs() < 1e-4);
+}
+
+#[test]
+fn limiter_holds_the_ceiling_and_recovers() {
+    let mut mixer = Mixer::new(48000);
+    let meter = mixer.meter();
+    let loud: Vec<[f32; 2]> = (0..4800).map(|_| mixer.mix([3., -2.], 1)).collect();
+    assert!(loud.iter().all(|el| (el[0] - 0.8).abs() < 1e-6));
+    // Both channels are reduced alike, so the balance stays.
+    assert!(loud.iter().all(|el| (el[1] / el[0] + 2. / 3.).abs() < 1e-5));
+    assert!((meter.peak() - 0.8).abs() < 1e-6);
+
+    // Right after the burst a quiet signal is still held down, then the release lets go.
+    let after = mixer.mix([0.4, 0.4], 1)[0];
+    assert!(after < 0.2, "{after}");
+    let released = (0..4 * 48000).map(|_| mixer.mix([0.4, 0.4], 1)[0]).last();
+    assert!((released.unwrap() - 0.4).abs() < 1e-4);
+    assert!((meter.rms() - 0.4).abs() < 1e-3);
+    meter.reset();
+    assert_eq!(meter.peak(), 0.);
+}
+
+#[test]
+fn limiter_bends_peaks_above_the_knee() {
+    let level = |input: f32| {
+        let mut mixer = Mixer::new(48000);
+        (0..480)
+            .map(|_| mixer.mix([input, input], 1)[0])
+            .last()
+            .unwrap()
+    };
+    assert_eq!(level(0.5), 0.5);
+    let bent = [0.6, 0.7, 0.9, 1.5, 10.].map(level);
+    assert!(bent[0] > 0.55 && bent[0] < 0.6, "{bent:?}");
+    assert!(bent.windows(2).all(|el| el[0] < el[1]), "{bent:?}");
+    assert!(bent.iter().all(|el| *el <= 0.8), "{bent:?}");
+}
+
+#[test]
+fn panning_keeps_the_power() {
+    let [left, right] = pan(1., 0.);
+    assert!((left - right).abs() < 1e-6);
+    assert!((left * left + right * right - 1.).abs() < 1e-6);
+    let [left, right] = pan(1., -1.);
+    assert!((left - 1.).abs() < 1e-6 && right.abs() < 1e-6);
+    assert_eq!(pan(1., 5.), pan(1., 1.));
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use chalaxata_rs::{
+    chord::FullChord,
+    modulation::{Lfo, Modulation, Modulator, voice_seed},
+    note::{Harmonym, parse_harmonym},
+    playable::PlayableChord,
+};
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+fn modulation(vibrato: Option<Lfo>, tremolo: Option<Lfo>) -> Modulation {
+    Modulation { vibrato, tremolo }
+}
+
+#[test]
+fn voices_are_seeded_the_same_way_every_time() {
+    assert_eq!(
+        voice_seed(harmonym("Chy"), 2),
+        voice_seed(harmonym("Chy"), 2)
+    );
+    assert_ne!(
+        voice_seed(harmonym("Chy"), 2),
+        voice_seed(harmonym("Chy"), 3)
+    );
+    assert_ne!(
+        voice_seed(harmonym("Chy"), 2),
+        voice_seed(harmonym("Fu"), 2)
+    );
+
+    let vibrato = modulation(Some(Lfo::new(5., 20.)), Some(Lfo::new(3., 0.5)));
+    let seed = voice_seed(harmonym("Ly"), 0);
+    let first: Vec<_> = Modulator::new(&vibrato, seed, 1000).take(500).collect();
+    let again: Vec<_> = Modulator::new(&vibrato, seed, 1000).take(500).collect();
+    assert_eq!(first, again);
+    let other: Vec<_> = Modulator::new(&vibrato, seed + 1, 1000).take(500).collect();
+    assert_ne!(first, other);
+}
+
+#[test]
+fn replayed_chords_get_new_phases_unless_seeded() {
+    let render = |seed: Option<u64>| -> Vec<f32> {
+        let mut chord: PlayableChord = FullChord {
+            tones: vec![harmonym("Ah"), harmonym("Ly")],
+            base: 220.,
+        }
+        .into();
+        chord.set_modulation(modulation(Some(Lfo::new(6., 30.)), None));
+        if let Some(seed) = seed {
+            chord.set_seed(seed);
+        }
+        chord.prerender();
+        chord.take(4000).collect()
+    };
+    assert_ne!(render(None), render(None));
+    assert_eq!(render(Some(5)), render(Some(5)));
+    assert_ne!(render(Some(5)), render(Some(6)));
+}
+
+#[test]
+fn tremolo_only_takes_gain_away() {
+    let tremolo = modulation(None, Some(Lfo::new(4., 0.6)));
+    let gains: Vec<f32> = Modulator::new(&tremolo, 7, 1000)
+        .take(1000)
+        .map(|(ratio, gain)| {
+            assert_eq!(ratio, 1.);
+            gain
+        })
+        .collect();
+    assert!(gains.iter().all(|el| (0.4 - 1e-6..=1.).contains(el)));
+    let low = gains.iter().copied().fold(1., f32::min);
+    let high = gains.iter().copied().fold(0., f32::max);
+    assert!(low < 0.41 && high > 0.99, "{low} to {high}");
+
+    // Depths beyond full level still never go below silence.
+    let deep = modulation(None, Some(Lfo::new(4., 3.)));
+    assert!(
+        Modulator::new(&deep, 7, 1000)
+            .take(1000)
+            .all(|(_, gain)| (0. ..=1.).contains(&gain))
+    );
+}
+
+#[test]
+fn vibrato_fades_in_over_its_delay() {
+    let vibrato = modulation(
+        Some(Lfo {
+            delay: Duration::from_millis(500),
+            ..Lfo::new(10., 50.)
+        }),
+        None,
+    );
+    let cents: Vec<f32> = Modulator::new(&vibrato, 3, 1000)
+        .take(1000)
+        .map(|(ratio, gain)| {
+            assert_eq!(gain, 1.);
+            1200. * ratio.log2()
+        })
+        .collect();
+    for (frame, el) in cents.iter().enumerate().take(500) {
+        assert!(
+            el.abs() <= 50. * (frame + 1) as f32 / 500. + 1e-3,
+            "{el} cents at {frame}"
+        );
+    }
+    assert!(cents[..50].iter().all(|el| el.abs() < 5.1));
+    let swing = cents[500..].iter().fold(0f32, |acc, el| acc.max(el.abs()));
+    assert!(swing > 49. && swing <= 50. + 1e-3, "{swing}");
+}
@@ -0,0 +1,94 @@
+use chalaxata_rs::{
+    lattice::Region,
+    note::{Harmonym, parse_harmonym},
+};
+use num::traits::Inv;
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+#[test]
+fn octave_marks_count_up_and_down() {
+    assert_eq!(harmonym("Ly+").degree(1), Some(1));
+    assert_eq!(harmonym("Chy++").degree(1), Some(2));
+    assert_eq!(harmonym("Su-").degree(1), Some(-1));
+    assert_eq!(harmonym("Ah+-").degree(1), Some(0));
+    assert_eq!(harmonym("Ly+").degree(3), Some(1));
+    assert!(parse_harmonym("Ly-+").is_err());
+}
+
+#[test]
+fn harmonyms_stay_within_the_named_range() {
+    assert!(Harmonym::from_exponents([3, -3, 3, -3, 3]).is_some());
+    assert!(Harmonym::from_exponents([0, 0, 0, 0, 10]).is_none());
+    assert!(Harmonym::from_exponents([-4, 0, 0, 0, 0]).is_none());
+    assert!(parse_harmonym("Ah++++").is_err());
+    assert!(parse_harmonym("Ah+++").is_ok());
+
+    let mut chy = harmonym("Chy");
+    assert_eq!(chy.set_degree(2, 4), None);
+    assert_eq!(chy.set_degree(6, 1), None);
+    assert_eq!(chy, harmonym("Chy"));
+    assert_eq!(chy.set_degree(2, 3), Some(()));
+    assert_eq!(chy, harmonym("Xcy"));
+
+    let scy = harmonym("Chy").checked_mul(harmonym("Chy")).unwrap();
+    assert_eq!(scy, harmonym("Scy"));
+    assert_eq!(scy.checked_div(harmonym("Chy")), Some(harmonym("Chy")));
+    assert_eq!(harmonym("Xcy").checked_mul(harmonym("Chy")), None);
+    assert_eq!(harmonym("Fu").checked_div(harmonym("Xcy")), None);
+    assert_eq!(harmonym("Fu").inv(), harmonym("Chy"));
+
+    assert_eq!(harmonym("Chy") * harmonym("Chy"), Some(scy));
+    assert_eq!(scy / harmonym("Chy"), Some(harmonym("Chy")));
+    assert_eq!(harmonym("Xcy") * harmonym("Chy"), None);
+    assert_eq!(harmonym("Fu") / harmonym("Xcy"), None);
+}
+
+#[test]
+fn every_harmonym_evaluates_without_overflow() {
+    for harmonym in Region::default().iter() {
+        let ratio: f32 = harmonym.eval().into();
+        let frequency = harmonym * 1.;
+        assert!(
+            (ratio / frequency - 1.).abs() < 1e-4,
+            "{harmonym:?} is {ratio} but plays at {frequency}"
+        );
+        assert!(((frequency.log2() * 1200.) - harmonym.cents()).abs() < 0.1);
+    }
+    let widest = Harmonym::from_exponents([3, 3, 3, 3, 3]).unwrap();
+    assert_eq!(widest.eval().to_string(), "1540798875:262144");
+}
+
+#[test]
+fn harmonyms_order_by_pitch() {
+    let mut tones = [
+        harmonym("Ly"),
+        harmonym("Ah"),
+        harmonym("Chy"),
+        harmonym("Su-"),
+    ];
+    tones.sort();
+    assert_eq!(
+        tones,
+        [
+            harmonym("Su-"),
+            harmonym("Ah"),
+            harmonym("Ly"),
+            harmonym("Chy")
+        ]
+    );
+    assert!((harmonym("Chy") * 440. - 660.).abs() < 1e-3);
+    assert!((harmonym("Ah+") * 440. - 880.).abs() < 1e-3);
+}
+
+#[test]
+fn names_survive_a_round_trip() {
+    for harmonym in Region::default().iter() {
+        let name = harmonym.to_string();
+        let parsed = parse_harmonym(&name).unwrap().1;
+        // The name leaves out the octave.
+        assert_eq!(parsed.exponents()[1..], harmonym.exponents()[1..], "{name}");
+    }
+}
@@ -0,0 +1,105 @@
+use chalaxata_rs::{
+    note::{Harmonym, parse_harmonym},
+    playable::{PlayOptions, Spread, Strum, StrumDirection},
+};
+
+fn harmonyms(names: &[&str]) -> Vec<Harmonym> {
+    names
+        .iter()
+        .map(|el| parse_harmonym(el).unwrap().1)
+        .collect()
+}
+
+fn strummed(direction: StrumDirection, rate: f32, curve: f32) -> PlayOptions {
+    PlayOptions {
+        strum: Some(Strum {
+            direction,
+            rate,
+            curve,
+            stroke: 0,
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn strums_start_voices_in_pitch_order() {
+    let chord = harmonyms(&["Chy", "Ah", "Ly"]);
+    assert_eq!(PlayOptions::default().starts(&chord, 1000), [0, 0, 0]);
+    // Ten voices per second at 1000 Hz put 100 frames between voices.
+    let up = strummed(StrumDirection::Up, 10., 1.);
+    assert_eq!(up.starts(&chord, 1000), [200, 0, 100]);
+    let down = strummed(StrumDirection::Down, 10., 1.);
+    assert_eq!(down.starts(&chord, 1000), [0, 200, 100]);
+
+    let mut alternating = strummed(StrumDirection::Alternating, 10., 1.);
+    assert_eq!(alternating.starts(&chord, 1000), [200, 0, 100]);
+    alternating.advance();
+    assert_eq!(alternating.starts(&chord, 1000), [0, 200, 100]);
+    alternating.advance();
+    assert_eq!(alternating.starts(&chord, 1000), [200, 0, 100]);
+
+    let single = harmonyms(&["Ly"]);
+    assert_eq!(up.starts(&single, 1000), [0]);
+}
+
+#[test]
+fn strum_curve_shapes_the_delays() {
+    let chord = harmonyms(&["Ah", "Ly", "Chy"]);
+    assert_eq!(
+        strummed(StrumDirection::Up, 10., 2.).starts(&chord, 1000),
+        [0, 50, 200]
+    );
+    assert_eq!(
+        strummed(StrumDirection::Up, 10., 0.5).starts(&chord, 1000),
+        [0, 141, 200]
+    );
+}
+
+#[test]
+fn unusable_strum_settings_fall_back_to_the_defaults() {
+    let chord = harmonyms(&["Ah", "Ly", "Chy"]);
+    let default = strummed(StrumDirection::Up, 15., 1.).starts(&chord, 1000);
+    for (rate, curve) in [
+        (0., 1.),
+        (-3., 1.),
+        (f32::NAN, 1.),
+        (15., -1.),
+        (15., 0.),
+        (15., f32::INFINITY),
+    ] {
+        assert_eq!(
+            strummed(StrumDirection::Up, rate, curve).starts(&chord, 1000),
+            default,
+            "rate {rate}, curve {curve}"
+        );
+    }
+    assert_eq!(default, [0, 66, 133]);
+}
+
+#[test]
+fn spreads_place_tones_across_the_stereo_field() {
+    let chord = harmonyms(&["Chy", "Ah", "Ly"]);
+    assert_eq!(Spread::Center.pans(&chord), [0., 0., 0.]);
+
+    let by_pitch = Spread::Pitch(1.).pans(&chord);
+    assert_eq!(by_pitch[0], 1.);
+    assert_eq!(by_pitch[1], -1.);
+    // The major third sits at 386 of the 702 cents between the outer tones.
+    assert!((by_pitch[2] - (2. * 386.31 / 701.96 - 1.)).abs() < 1e-3);
+    assert_eq!(Spread::Pitch(0.5).pans(&chord)[0], 0.5);
+
+    // Fifths pull right and thirds left, whatever their pitch.
+    let lattice = Spread::Lattice(0.5).pans(&harmonyms(&["Chy", "Ly", "Ah"]));
+    assert_eq!(lattice[0], 0.5);
+    assert_eq!(lattice[1], -0.5);
+    assert!(lattice[2] > -0.5 && lattice[2] < 0.5);
+
+    // Tones that do not differ along any panned dimension stay in the middle.
+    assert_eq!(
+        Spread::Lattice(1.).pans(&harmonyms(&["Ah", "Ah+"])),
+        [0., 0.]
+    );
+    assert_eq!(Spread::Pitch(1.).pans(&harmonyms(&["Ly"])), [0.]);
+    assert!(Spread::Pitch(1.).pans(&[]).is_empty());
+}
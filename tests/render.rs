@@ -0,0 +1,67 @@
+use chalaxata_rs::{
+    note::Harmonym,
+    render::BlockRenderer,
+    score::{NoteDuration, PlayableScore, Score},
+};
+
+/// Sixty overlapping notes around the lattice, with a silent gap in the middle.
+fn score() -> Score {
+    let mut score = Score::new();
+    for idx in 0..60u32 {
+        // A second at the default 120 bpm.
+        let gap = if idx >= 30 { 1920 } else { 0 };
+        let exponents = [
+            (idx % 3) as i8 - 1,
+            (idx % 5) as i8 - 2,
+            (idx % 4) as i8 - 2,
+            0,
+            0,
+        ];
+        score.push((
+            NoteDuration {
+                start: idx * 60 + gap,
+                length: 150 + idx * 13,
+            },
+            Harmonym::from_exponents(exponents).unwrap(),
+        ));
+    }
+    score
+}
+
+fn render(threads: usize, block_frames: usize) -> Vec<f32> {
+    let mut renderer = BlockRenderer::from(score());
+    renderer.set_threads(threads);
+    renderer.set_block_frames(block_frames);
+    let mut samples = Vec::new();
+    while let Some(block) = renderer.next_block() {
+        samples.extend_from_slice(block);
+    }
+    samples
+}
+
+#[test]
+fn block_render_does_not_depend_on_thread_count() {
+    let single = render(1, 1000);
+    assert!(single.iter().any(|el| el.abs() > 0.1));
+    for threads in [2, 3, 8] {
+        assert!(render(threads, 1000) == single, "{threads} threads differ");
+    }
+}
+
+#[test]
+fn block_render_matches_sample_by_sample_playback() {
+    let expected: Vec<f32> = PlayableScore::from(score()).collect();
+    for block_frames in [64, 777, 4096] {
+        let rendered = render(4, block_frames);
+        assert_eq!(rendered.len(), expected.len());
+        let error = rendered
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f32::max);
+        assert!(
+            error < 1e-5,
+            "off by {error} with {block_frames}-frame blocks"
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use chalaxata_rs::time::{Position, TICKS_PER_QUARTER, TempoMap, TimeSignature, note_length};
+
+#[test]
+fn ramp_takes_the_time_of_its_average_tempo() {
+    let mut tempo = TempoMap::new(60.);
+    let bar = note_length(1, 1);
+    tempo.ramp_tempo(bar, 2 * bar, 180.);
+    assert!((tempo.seconds(bar) - 4.).abs() < 1e-9);
+    // Four beats going from 60 to 180 bpm take 4 * ln(3) / 2 seconds.
+    let ramp = tempo.seconds(2 * bar) - tempo.seconds(bar);
+    assert!((ramp - 2. * 3f64.ln()).abs() < 1e-9, "ramp took {ramp} s");
+    assert!((tempo.tempo_at(bar + 2 * TICKS_PER_QUARTER) - 120.).abs() < 1e-9);
+    // A quarter note at 180 bpm after the ramp.
+    let after = tempo.seconds(2 * bar + TICKS_PER_QUARTER) - tempo.seconds(2 * bar);
+    assert!((after - 1. / 3.).abs() < 1e-9);
+    for at in [0, bar / 3, bar + 1234, 2 * bar + 777] {
+        assert_eq!(tempo.ticks_at(tempo.seconds(at)), at);
+    }
+}
+
+#[test]
+fn positions_follow_time_signature_changes() {
+    let mut tempo = TempoMap::default();
+    tempo.set_time_signature(3, TimeSignature { beats: 6, unit: 8 });
+    let position = Position {
+        bar: 4,
+        beat: 5,
+        tick: 10,
+    };
+    // Two bars of 4/4, one of 6/8 and four eighths.
+    let at = 2 * note_length(1, 1) + note_length(6, 8) + note_length(4, 8) + 10;
+    assert_eq!(tempo.ticks(position), at);
+    assert_eq!(tempo.position(at), position);
+    assert_eq!(tempo.position(0).to_string(), "1.1.000");
+}
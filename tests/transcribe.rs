@@ -0,0 +1,86 @@
+use std::f32::consts::TAU;
+
+use chalaxata_rs::{
+    DEFAULT_BASE,
+    note::{Harmonym, parse_harmonym},
+    transcribe::{TranscribeError, TranscribeOptions, transcribe},
+};
+
+const SAMPLE_RATE: u32 = 44100;
+
+fn harmonym(name: &str) -> Harmonym {
+    parse_harmonym(name).unwrap().1
+}
+
+/// One second of equally loud sines at the given harmonyms above the default base.
+fn tones(names: &[&str]) -> Vec<f32> {
+    (0..SAMPLE_RATE)
+        .map(|frame| {
+            let time = frame as f32 / SAMPLE_RATE as f32;
+            names
+                .iter()
+                .map(|el| 0.4 * (TAU * (harmonym(el) * DEFAULT_BASE) * time).sin())
+                .sum()
+        })
+        .collect()
+}
+
+#[test]
+fn sine_becomes_a_single_note() {
+    let score = transcribe(&tones(&["Chy"]), SAMPLE_RATE, &TranscribeOptions::default()).unwrap();
+    let notes: Vec<_> = score.notes().iter().map(|el| el.harmonym).collect();
+    assert_eq!(notes, [harmonym("Chy")]);
+    let length = score.tempo().seconds(score.notes()[0].duration.length);
+    assert!(length > 0.8 && length <= 1., "held for {length} s");
+}
+
+#[test]
+fn two_tones_become_a_chord() {
+    let options = TranscribeOptions {
+        polyphonic: true,
+        ..Default::default()
+    };
+    let score = transcribe(&tones(&["Ah", "Ly"]), SAMPLE_RATE, &options).unwrap();
+    let notes: Vec<_> = score.notes().iter().map(|el| el.harmonym).collect();
+    assert_eq!(notes, [harmonym("Ah"), harmonym("Ly")]);
+    assert_eq!(
+        score.notes()[0].duration.start,
+        score.notes()[1].duration.start
+    );
+}
+
+#[test]
+fn unusable_options_and_input_are_refused() {
+    let samples = tones(&["Ah"]);
+    for options in [
+        TranscribeOptions {
+            window: 1,
+            ..Default::default()
+        },
+        TranscribeOptions {
+            hop: 0,
+            ..Default::default()
+        },
+        TranscribeOptions {
+            polyphonic: true,
+            window: 3000,
+            ..Default::default()
+        },
+    ] {
+        assert!(matches!(
+            transcribe(&samples, SAMPLE_RATE, &options),
+            Err(TranscribeError::InvalidOptions(_))
+        ));
+    }
+    assert!(matches!(
+        transcribe(&samples[..1000], SAMPLE_RATE, &TranscribeOptions::default()),
+        Err(TranscribeError::TooShort {
+            samples: 1000,
+            window: 4096
+        })
+    ));
+    assert!(matches!(
+        transcribe(&[], SAMPLE_RATE, &TranscribeOptions::default()),
+        Err(TranscribeError::Empty)
+    ));
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use chalaxata_rs::{
+    oscillator::Oscillator,
+    playable::{Bandlimit, Waveform},
+    wavetable::{self, TABLE_SIZE},
+};
+
+// The cache is shared by the whole process, so everything that touches it runs in this one test.
+#[test]
+fn cache_counts_hits_misses_and_evictions() {
+    wavetable::clear();
+
+    let saw = wavetable::ladder(Waveform::Saw, 48000);
+    // Every waveform but the sine holds as many tables at the same rate, so this leaves room for two.
+    wavetable::set_capacity(2 * saw.bytes());
+    assert_eq!(wavetable::stats().misses, 1);
+    assert!(Arc::ptr_eq(&saw, &wavetable::ladder(Waveform::Saw, 48000)));
+    assert_eq!(wavetable::stats().hits, 1);
+
+    wavetable::ladder(Waveform::Square, 48000);
+    wavetable::ladder(Waveform::Triangle, 48000);
+    let stats = wavetable::stats();
+    assert_eq!((stats.misses, stats.evictions, stats.ladders), (3, 1, 2));
+    assert_eq!(stats.bytes(), stats.tables * TABLE_SIZE * 4);
+    assert_eq!(stats.bytes(), 2 * saw.bytes());
+    // The saw was used least recently, so it had to go, while the square stays.
+    wavetable::ladder(Waveform::Square, 48000);
+    assert_eq!(wavetable::stats().hits, 2);
+    assert!(!Arc::ptr_eq(&saw, &wavetable::ladder(Waveform::Saw, 48000)));
+    assert_eq!(wavetable::stats().misses, 4);
+
+    // Low notes get no more harmonics than a table can hold, and notes near Nyquist get a plain sine.
+    let low = saw.table(saw.band(1.));
+    let peak = low.iter().fold(0f32, |acc, el| acc.max(el.abs()));
+    assert!(peak < 1.2, "{peak}");
+    let high = saw.table(saw.band(20000.));
+    for (idx, el) in high.iter().enumerate() {
+        let sine = 2. / std::f32::consts::PI
+            * (2. * std::f32::consts::PI * idx as f32 / TABLE_SIZE as f32).sin();
+        assert!((el - sine).abs() < 1e-4);
+    }
+
+    // Voices pick their tables when they start, and gliding only moves between them.
+    wavetable::set_capacity(4 * saw.bytes());
+    let mut oscillator = Oscillator::new(100., Waveform::Saw, Bandlimit::Additive, 48000);
+    let before = wavetable::stats();
+    for frequency in (50..15000).step_by(50) {
+        oscillator.set_frequency(frequency as f32);
+        oscillator.next();
+    }
+    assert_eq!(wavetable::stats(), before);
+
+    wavetable::clear();
+    let stats = wavetable::stats();
+    assert_eq!((stats.hits, stats.misses, stats.ladders), (0, 0, 0));
+    assert_eq!(stats.capacity, 4 * saw.bytes());
+    wavetable::set_capacity(wavetable::DEFAULT_CAPACITY);
+}